@@ -0,0 +1,21 @@
+//! Checks that reveal.js has been downloaded before building it in.
+
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if std::env::var_os("CARGO_FEATURE_BUNDLED_REVEAL_JS").is_none() {
+        return;
+    }
+    println!("cargo:rerun-if-changed=vendor/reveal.js");
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR not set");
+    let reveal_js = Path::new(&manifest_dir).join("vendor/reveal.js/dist/reveal.js");
+    if !reveal_js.is_file() {
+        eprintln!(
+            "error: the bundled-reveal-js feature needs a copy of reveal.js in vendor/reveal.js, \
+             which isn't included in the mdslides crate. Build from a clone of the mdslides \
+             repository, after running vendor/update-reveal-js.sh to download reveal.js."
+        );
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,426 @@
+//! Course agendas, which set out the chapters covered in each session of a
+//! multi-day course
+//!
+//! Agendas are given in `[[agendas]]` in `mdslides.toml`. Each one is written
+//! to its own page, with start and end times for every session worked out
+//! from the estimated time to present each chapter.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::config::AgendaConfig;
+use crate::template::escape_html;
+use crate::{
+    deck_url, page_depth, relative_url, timing, Book, DeckSummary, Error, IndexEntry, Template,
+};
+
+/// An agenda, with the times worked out
+#[derive(Debug, serde::Serialize)]
+struct Schedule<'a> {
+    title: &'a str,
+    days: Vec<Day<'a>>,
+    /// The length of the whole course, in seconds
+    time: u32,
+    duration: String,
+}
+
+/// A day of an agenda
+#[derive(Debug, serde::Serialize)]
+struct Day<'a> {
+    title: &'a str,
+    start: Option<String>,
+    end: Option<String>,
+    /// The length of the day, including breaks, in seconds
+    time: u32,
+    duration: String,
+    sessions: Vec<Session<'a>>,
+}
+
+/// A session or break in a day of an agenda
+#[derive(Debug, serde::Serialize)]
+struct Session<'a> {
+    /// `session` or `break`
+    kind: &'static str,
+    title: &'a str,
+    start: Option<String>,
+    end: Option<String>,
+    /// The length of the session, in seconds
+    time: u32,
+    duration: String,
+    chapters: Vec<Chapter<'a>>,
+}
+
+/// A chapter covered in a session
+#[derive(Debug, serde::Serialize)]
+struct Chapter<'a> {
+    title: &'a str,
+    number: String,
+    url: String,
+    slide_count: usize,
+    time: u32,
+    duration: String,
+    description: Option<&'a str>,
+}
+
+/// Read a time of day like `09:30`, as seconds since midnight.
+pub(crate) fn parse_time_of_day(text: &str) -> Option<u32> {
+    let (hours, minutes) = text.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 3600 + minutes * 60)
+}
+
+/// Write seconds since midnight as a time of day like `09:30`.
+fn format_time_of_day(seconds: u32) -> String {
+    format!("{:02}:{:02}", seconds / 3600 % 24, seconds / 60 % 60)
+}
+
+/// Work out when each session of an agenda starts and ends.
+///
+/// * `decks` - a summary of each chapter's deck, by its path in `SUMMARY.md`
+/// * `extension` - the file extension of the decks
+///
+/// Links to the decks are relative to the agenda's page.
+fn schedule<'a>(
+    agenda: &'a AgendaConfig,
+    book: &'a Book,
+    decks: &'a BTreeMap<&str, DeckSummary>,
+    extension: &str,
+) -> Result<Schedule<'a>, Error> {
+    let chapters: BTreeMap<&str, (&str, &str, String)> = book
+        .index_entries
+        .iter()
+        .filter_map(|entry| match entry {
+            IndexEntry::Chapter {
+                title,
+                path,
+                number,
+            } if !path.is_empty() => Some((
+                path.trim_start_matches("./"),
+                (title.as_str(), path.as_str(), number.to_string()),
+            )),
+            _ => None,
+        })
+        .collect();
+
+    let depth = page_depth(&agenda.file);
+    let mut days = Vec::new();
+    for day in agenda.days.iter() {
+        let day_start = day.start.as_deref().and_then(parse_time_of_day);
+        let mut clock = day_start;
+        let mut sessions = Vec::new();
+        for session in day.sessions.iter() {
+            let start = match session.start.as_deref().and_then(parse_time_of_day) {
+                Some(start) => {
+                    if clock.is_some_and(|clock| clock > start) {
+                        log::warn!(
+                            "{}: {:?} is due to start before the session before it ends",
+                            agenda.title,
+                            session.title
+                        );
+                    }
+                    Some(start)
+                }
+                None => clock,
+            };
+            let mut session_chapters = Vec::new();
+            for path in session.chapters.iter() {
+                let Some((title, path, number)) = chapters.get(path.trim_start_matches("./"))
+                else {
+                    return Err(Error::BadAgenda(format!(
+                        "{:?} in {:?} is not a chapter in SUMMARY.md",
+                        path, agenda.title
+                    )));
+                };
+                let deck = decks.get(path);
+                let time = deck.map_or(0, |deck| deck.time);
+                session_chapters.push(Chapter {
+                    title,
+                    number: number.clone(),
+                    url: relative_url(&deck_url(path, extension), depth),
+                    slide_count: deck.map_or(0, |deck| deck.slide_count),
+                    time,
+                    duration: timing::format_duration(time),
+                    description: deck.and_then(|deck| deck.description.as_deref()),
+                });
+            }
+            let time = match session.break_minutes {
+                Some(minutes) => minutes * 60,
+                None => session_chapters.iter().map(|chapter| chapter.time).sum(),
+            };
+            let end = start.map(|start| start + time);
+            clock = end;
+            sessions.push(Session {
+                kind: if session.break_minutes.is_some() {
+                    "break"
+                } else {
+                    "session"
+                },
+                title: &session.title,
+                start: start.map(format_time_of_day),
+                end: end.map(format_time_of_day),
+                time,
+                duration: timing::format_duration(time),
+                chapters: session_chapters,
+            });
+        }
+        // The day starts when its first session does, if that was given
+        let first_start = day
+            .sessions
+            .first()
+            .and_then(|session| session.start.as_deref())
+            .and_then(parse_time_of_day);
+        let start = day_start.or(first_start);
+        let time = match (start, clock) {
+            (Some(start), Some(end)) => end.saturating_sub(start),
+            _ => sessions.iter().map(|session| session.time).sum(),
+        };
+        log::info!(
+            "{}: {} takes about {}",
+            agenda.title,
+            day.title,
+            timing::format_duration(time)
+        );
+        days.push(Day {
+            title: &day.title,
+            start: start.map(format_time_of_day),
+            end: clock.map(format_time_of_day),
+            time,
+            duration: timing::format_duration(time),
+            sessions,
+        });
+    }
+    let time = days.iter().map(|day| day.time).sum();
+    Ok(Schedule {
+        title: &agenda.title,
+        days,
+        time,
+        duration: timing::format_duration(time),
+    })
+}
+
+/// Write an agenda as HTML, for legacy templates.
+fn agenda_html(schedule: &Schedule) -> String {
+    let times = |start: &Option<String>, end: &Option<String>, duration: &str| match (start, end) {
+        (Some(start), Some(end)) => format!("{} - {}, {}", start, end, duration),
+        _ => duration.to_owned(),
+    };
+    let mut html = String::new();
+    for day in schedule.days.iter() {
+        html.push_str(&format!(
+            "<h2>{} <small>({})</small></h2>\n",
+            escape_html(day.title),
+            times(&day.start, &day.end, &day.duration)
+        ));
+        for session in day.sessions.iter() {
+            html.push_str(&format!(
+                "<h3>{} <small>({})</small></h3>\n",
+                escape_html(session.title),
+                times(&session.start, &session.end, &session.duration)
+            ));
+            if session.chapters.is_empty() {
+                continue;
+            }
+            html.push_str("<ul>\n");
+            for chapter in session.chapters.iter() {
+                html.push_str(&format!(
+                    "<li><a href=\"{}\">{}</a> <small>(about {})</small></li>\n",
+                    escape_html(&chapter.url),
+                    escape_html(chapter.title),
+                    chapter.duration
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+    }
+    html
+}
+
+/// Render an agenda page.
+///
+/// A legacy template may contain `$AGENDA`, which is replaced with HTML
+/// headings for each day and session and lists of their chapters, and
+/// `$TITLE` (or `$RAW_TITLE`), the title of the agenda.
+///
+/// A Jinja template is given the variables `title`, `agenda` (the same HTML,
+/// not escaped) and `days`, each with a `title`, `start`, `end`, `time`,
+/// `duration` and `sessions`. Each session has a `kind` (`session` or
+/// `break`), a `title`, `start`, `end`, `time`, `duration` and `chapters`,
+/// which are like the chapters in an index template's `entries`. The
+/// template is also given `total_time` and `total_duration` for the whole
+/// agenda, replacing those for the book, along with anything in `context`.
+fn render(
+    schedule: &Schedule,
+    template: &Template,
+    context: minijinja::Value,
+) -> Result<String, Error> {
+    let html = agenda_html(schedule);
+    let context = minijinja::context! {
+        title => schedule.title,
+        agenda => minijinja::Value::from_safe_string(html.clone()),
+        days => &schedule.days,
+        total_time => schedule.time,
+        total_duration => &schedule.duration,
+        ..context
+    };
+    template.render(
+        &[
+            ("AGENDA", &html),
+            ("TITLE", &escape_html(schedule.title)),
+            ("RAW_TITLE", schedule.title),
+        ],
+        context,
+    )
+}
+
+/// Generate a page for each agenda in `mdslides.toml`.
+///
+/// * `decks` - a summary of each chapter's deck, by its path in `SUMMARY.md`
+/// * `extension` - the file extension of the decks
+/// * `context` - variables for every template, given how many folders below
+///   the output directory the page is
+pub(crate) fn generate_agendas(
+    book: &Book,
+    output_dir: &Path,
+    decks: &BTreeMap<&str, DeckSummary>,
+    extension: &str,
+    context: &dyn Fn(usize) -> minijinja::Value,
+) -> Result<(), Error> {
+    for agenda in book.config.agendas.iter() {
+        log::info!("Processing agenda {:?}", agenda.title);
+        let template = match agenda.template.as_ref() {
+            Some(path) => Template::load(
+                &book.root.join(path),
+                book.config.slides.template_syntax.unwrap_or_default(),
+            )?,
+            None => Template::builtin_agenda(),
+        };
+        let schedule = schedule(agenda, book, decks, extension)?;
+        let generated = render(&schedule, &template, context(page_depth(&agenda.file)))?;
+        let out_path = output_dir.join(&agenda.file);
+        if let Some(parent) = out_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(out_path, generated)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn times_of_day() {
+        assert_eq!(parse_time_of_day("09:30"), Some(34200));
+        assert_eq!(parse_time_of_day("9:05"), Some(32700));
+        assert_eq!(parse_time_of_day("24:00"), None);
+        assert_eq!(parse_time_of_day("noon"), None);
+        assert_eq!(format_time_of_day(34200 + 95), "09:31");
+    }
+
+    #[test]
+    fn agenda() {
+        let book = Book {
+            root: "book".into(),
+            title: "Rust".to_owned(),
+            authors: Vec::new(),
+            language: None,
+            src_dir: "book/src".into(),
+            index_entries: vec![
+                IndexEntry::Chapter {
+                    title: "Intro".to_owned(),
+                    path: "./intro.md".to_owned(),
+                    number: crate::SectionNumber(vec![1]),
+                },
+                IndexEntry::Chapter {
+                    title: "Box<T> & Rc<T>".to_owned(),
+                    path: "./pointers.md".to_owned(),
+                    number: crate::SectionNumber(vec![2]),
+                },
+            ],
+            config: toml::from_str(
+                r#"
+                [[agendas]]
+                title = "Two days"
+                file = "agenda.html"
+
+                [[agendas.days]]
+                title = "Day 1"
+                start = "09:00"
+                sessions = [
+                    { title = "Welcome", chapters = ["intro.md"] },
+                    { title = "Coffee", break = 15 },
+                    { title = "Pointers", start = "11:00", chapters = ["./pointers.md"] },
+                ]
+
+                [[agendas.days]]
+                title = "Day 2"
+                sessions = [{ title = "Recap", chapters = ["./intro.md"] }]
+                "#,
+            )
+            .unwrap(),
+        };
+        let mut decks = BTreeMap::new();
+        decks.insert(
+            "./intro.md",
+            DeckSummary {
+                slide_count: 10,
+                time: 1800,
+                description: None,
+            },
+        );
+        decks.insert(
+            "./pointers.md",
+            DeckSummary {
+                slide_count: 20,
+                time: 3600,
+                description: None,
+            },
+        );
+        let course = schedule(&book.config.agendas[0], &book, &decks, "html").unwrap();
+        assert_eq!(
+            agenda_html(&course),
+            "<h2>Day 1 <small>(09:00 - 12:00, 3h 00m)</small></h2>\n\
+            <h3>Welcome <small>(09:00 - 09:30, 30m 00s)</small></h3>\n\
+            <ul>\n\
+            <li><a href=\"./intro.html\">Intro</a> <small>(about 30m 00s)</small></li>\n\
+            </ul>\n\
+            <h3>Coffee <small>(09:30 - 09:45, 15m 00s)</small></h3>\n\
+            <h3>Pointers <small>(11:00 - 12:00, 1h 00m)</small></h3>\n\
+            <ul>\n\
+            <li><a href=\"./pointers.html\">Box&lt;T&gt; &amp; Rc&lt;T&gt;</a> <small>(about 1h 00m)</small></li>\n\
+            </ul>\n\
+            <h2>Day 2 <small>(30m 00s)</small></h2>\n\
+            <h3>Recap <small>(30m 00s)</small></h3>\n\
+            <ul>\n\
+            <li><a href=\"./intro.html\">Intro</a> <small>(about 30m 00s)</small></li>\n\
+            </ul>\n"
+        );
+        assert_eq!(course.duration, "3h 30m");
+
+        let template = Template::new(
+            "{% for day in days %}{{ day.title }}:{% for s in day.sessions %} {{ s.kind }} {{ s.duration }}{% endfor %}\n{% endfor %}{{ total_duration }}",
+            crate::TemplateSyntax::Auto,
+        );
+        assert_eq!(
+            render(&course, &template, minijinja::Value::UNDEFINED).unwrap(),
+            "Day 1: session 30m 00s break 15m 00s session 1h 00m\nDay 2: session 30m 00s\n3h 30m"
+        );
+
+        // Agendas in sub-folders link back up to the decks
+        let mut config = book.config.agendas[0].clone();
+        config.file = "course/agenda.html".into();
+        let course = schedule(&config, &book, &decks, "html").unwrap();
+        assert_eq!(course.days[0].sessions[0].chapters[0].url, "../intro.html");
+
+        let mut config = book.config.agendas[0].clone();
+        config.days[0].sessions[0]
+            .chapters
+            .push("./missing.md".to_owned());
+        assert!(matches!(
+            schedule(&config, &book, &decks, "html"),
+            Err(Error::BadAgenda(_))
+        ));
+    }
+}
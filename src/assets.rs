@@ -0,0 +1,216 @@
+//! Copying the images and other files used by chapters into the output
+//!
+//! Decks are written to the same relative location in the output directory
+//! as their chapters have in the book's `src` folder, so a file the chapter
+//! refers to with a relative URL is copied to the same relative location too.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::{percent_decode, Book, Error};
+
+/// Find the local files a chapter refers to.
+///
+/// These are the URLs of Markdown images and links, and the `src` of any
+/// HTML `<img>` tags, which are relative paths rather than absolute URLs or
+/// links within the page. Links to other chapters are left out.
+pub fn local_references(markdown: &str) -> Vec<String> {
+    references(markdown)
+        .into_iter()
+        .filter_map(|url| {
+            // Drop any fragment or query string
+            let path = url.split(['#', '?']).next().unwrap_or_default();
+            let is_local = !path.is_empty()
+                && !path.starts_with('/')
+                && !path.contains(':')
+                && !path.ends_with(".md");
+            is_local.then(|| path.to_owned())
+        })
+        .collect()
+}
+
+/// Find the `http` and `https` URLs a chapter refers to.
+pub fn external_references(markdown: &str) -> Vec<String> {
+    references(markdown)
+        .into_iter()
+        .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+        .collect()
+}
+
+/// Find the URLs of all the images and links in some Markdown, including
+/// HTML `<img>` tags.
+fn references(markdown: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    for event in pulldown_cmark::Parser::new(markdown) {
+        match event {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Image { dest_url, .. })
+            | pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link { dest_url, .. }) => {
+                urls.push(dest_url.into_string());
+            }
+            pulldown_cmark::Event::Html(html) | pulldown_cmark::Event::InlineHtml(html) => {
+                urls.extend(img_sources(&html));
+            }
+            _ => {}
+        }
+    }
+    urls
+}
+
+/// Find the `src` attributes of any `<img>` tags in some HTML.
+fn img_sources(html: &str) -> Vec<String> {
+    let mut sources = Vec::new();
+    let mut rest = html;
+    while let Some(idx) = rest.find("<img") {
+        rest = &rest[idx + 4..];
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+        let Some(src_idx) = tag.find("src=") else {
+            continue;
+        };
+        let value = &tag[src_idx + 4..];
+        let source = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next(),
+            _ => value.split_whitespace().next(),
+        };
+        if let Some(source) = source {
+            sources.push(source.to_owned());
+        }
+    }
+    sources
+}
+
+/// Copy the local files a chapter refers to into the output directory.
+///
+/// * `chapter` - the chapter's path in `SUMMARY.md`
+/// * `content` - the chapter's Markdown
+pub fn copy_chapter_assets(
+    book: &Book,
+    chapter: &str,
+    content: &str,
+    output_dir: &Path,
+) -> Result<(), Error> {
+    let chapter_file = crate::chapter_file(chapter);
+    let chapter_dir = chapter_file.parent().unwrap_or(Path::new(""));
+    for reference in local_references(content) {
+        let Some(relative) = normalise(&chapter_dir.join(percent_decode(&reference))) else {
+            log::warn!(
+                "Not copying {:?} from {}, as it is outside the book's src folder",
+                reference,
+                chapter
+            );
+            continue;
+        };
+        let from = book.src_dir.join(&relative);
+        if !from.is_file() {
+            log::debug!(
+                "Not copying {:?} from {}, as it isn't a file",
+                reference,
+                chapter
+            );
+            continue;
+        }
+        let to = output_dir.join(&relative);
+        log::info!("Copying {}", relative.display());
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&from, &to)?;
+    }
+    Ok(())
+}
+
+/// Copy the `static_dirs` from `mdslides.toml` into the output directory.
+pub fn copy_static_dirs(book: &Book, output_dir: &Path) -> Result<(), Error> {
+    for dir in book.config.slides.static_dirs.iter() {
+        let Some(relative) = normalise(dir) else {
+            log::warn!(
+                "Not copying {}, as it is outside the book's src folder",
+                dir.display()
+            );
+            continue;
+        };
+        log::info!("Copying {}", relative.display());
+        crate::copy_dir(&book.src_dir.join(&relative), &output_dir.join(&relative))?;
+    }
+    Ok(())
+}
+
+/// The copy of reveal.js built into mdslides, from `vendor/reveal.js`
+#[cfg(feature = "bundled-reveal-js")]
+static BUNDLED_REVEAL_JS: include_dir::Dir =
+    include_dir::include_dir!("$CARGO_MANIFEST_DIR/vendor/reveal.js");
+
+/// Write the copy of reveal.js built into mdslides into a folder.
+#[cfg(feature = "bundled-reveal-js")]
+pub fn write_bundled_reveal_js(to: &Path) -> Result<(), Error> {
+    std::fs::create_dir_all(to)?;
+    BUNDLED_REVEAL_JS.extract(to)?;
+    Ok(())
+}
+
+/// Write the copy of reveal.js built into mdslides into a folder.
+#[cfg(not(feature = "bundled-reveal-js"))]
+pub fn write_bundled_reveal_js(_to: &Path) -> Result<(), Error> {
+    Err(Error::NoBundledRevealJs)
+}
+
+/// Remove any `.` and `..` from a relative path.
+///
+/// Returns `None` if the path is absolute, or goes above where it starts.
+pub(crate) fn normalise(path: &Path) -> Option<PathBuf> {
+    let mut normalised = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(name) => normalised.push(name),
+            Component::ParentDir => {
+                if !normalised.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(normalised)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn references() {
+        let markdown = r#"# Ownership
+
+![Diagram](./img/ownership.svg "Ownership") and [the handout](files/handout.pdf#page=2)
+
+<img class="big" src="img/ferris.png">
+
+[Next chapter](./chapter_2.md), [Rust](https://www.rust-lang.org), [top](#ownership)
+"#;
+        assert_eq!(
+            local_references(markdown),
+            ["./img/ownership.svg", "files/handout.pdf", "img/ferris.png"]
+        );
+    }
+
+    #[test]
+    fn normalising() {
+        assert_eq!(
+            normalise(Path::new("./part/../img/./a.png")),
+            Some(PathBuf::from("img/a.png"))
+        );
+        assert_eq!(normalise(Path::new("part/../../secret.txt")), None);
+    }
+
+    #[test]
+    fn bundled_reveal_js() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = write_bundled_reveal_js(dir.path());
+        if cfg!(feature = "bundled-reveal-js") {
+            result.unwrap();
+            assert!(dir.path().join("dist/reveal.js").is_file());
+            assert!(dir.path().join("plugin/markdown/markdown.js").is_file());
+        } else {
+            assert!(matches!(result, Err(Error::NoBundledRevealJs)));
+        }
+    }
+}
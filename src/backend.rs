@@ -0,0 +1,162 @@
+//! Turning slides into Markdown for a particular slide framework
+
+use crate::slides::{Block, Diagram, Slide};
+use crate::Error;
+
+/// The slide frameworks we can generate decks for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// reveal.js, with its Markdown plugin
+    #[default]
+    Reveal,
+    /// Marp - produces a Markdown file for `marp-cli`, without using a template
+    Marp,
+    /// remark.js
+    Remark,
+}
+
+impl Backend {
+    /// The file extension of the decks generated by this backend
+    pub fn extension(self) -> &'static str {
+        match self {
+            Backend::Reveal | Backend::Remark => "html",
+            Backend::Marp => "md",
+        }
+    }
+
+    /// Does this backend insert the slides into an HTML template?
+    pub fn uses_template(self) -> bool {
+        match self {
+            Backend::Reveal | Backend::Remark => true,
+            Backend::Marp => false,
+        }
+    }
+
+    /// The URL fragment which shows the slide with the given index (counting
+    /// from zero) in a deck.
+    ///
+    /// remark.js numbers each step of a slide split with `--` lines as a slide
+    /// of its own, so `increments` is how many of those lines there are on
+    /// the slides before this one (see [`Slide::increments`]).
+    pub fn slide_anchor(self, index: usize, increments: usize) -> String {
+        match self {
+            Backend::Reveal => format!("#/{}", index),
+            Backend::Marp => format!("#{}", index + 1),
+            Backend::Remark => format!("#{}", index + increments + 1),
+        }
+    }
+
+    /// Convert slides into Markdown, in the dialect this backend expects.
+    ///
+    /// The `render_diagram` function is called for each diagram, and should
+    /// return the HTML to put in its place. The first error it returns is
+    /// returned from here.
+    pub fn render(
+        self,
+        title: &str,
+        slides: &[Slide],
+        render_diagram: &mut dyn FnMut(&Diagram) -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        let mut markdown = String::new();
+        if self == Backend::Marp {
+            markdown.push_str("---\nmarp: true\n");
+            markdown.push_str(&format!("title: {}\n", yaml_string(title)));
+            markdown.push_str("---\n\n");
+        }
+        for (idx, slide) in slides.iter().enumerate() {
+            // Don't put a --- before the first slide
+            if idx != 0 {
+                markdown.push_str("---\n");
+            }
+            if !slide.classes.is_empty() {
+                match self {
+                    Backend::Reveal => markdown.push_str(&format!(
+                        "<!-- .slide: class=\"{}\" -->\n",
+                        slide.classes.join(" ")
+                    )),
+                    Backend::Marp => markdown
+                        .push_str(&format!("<!-- _class: {} -->\n", slide.classes.join(" "))),
+                    Backend::Remark => {
+                        markdown.push_str(&format!("class: {}\n", slide.classes.join(", ")))
+                    }
+                }
+            }
+            for block in slide.blocks.iter() {
+                match block {
+                    Block::Markdown(text) => markdown.push_str(text),
+                    Block::Diagram(diagram) => markdown.push_str(&render_diagram(diagram)?),
+                }
+            }
+            if let Some(notes) = slide.notes.as_ref() {
+                match self {
+                    Backend::Reveal => {
+                        markdown.push_str("Note:\n");
+                        markdown.push_str(notes);
+                    }
+                    Backend::Marp => {
+                        markdown.push_str("<!--\n");
+                        markdown.push_str(&notes.replace("-->", "-- >"));
+                        markdown.push_str("-->\n");
+                    }
+                    Backend::Remark => {
+                        markdown.push_str("???\n");
+                        markdown.push_str(notes);
+                    }
+                }
+            }
+        }
+        Ok(markdown)
+    }
+}
+
+/// Write text as a YAML double-quoted string.
+fn yaml_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render(backend: Backend) -> String {
+        let slides = crate::slides::split(
+            "# Page 1\n<!-- .slide: class=\"center middle\" -->\nText\nNote: Hello\n## Page 2\n",
+            &[],
+        );
+        backend
+            .render("Chapter", &slides, &mut |_| Ok(String::new()))
+            .unwrap()
+    }
+
+    #[test]
+    fn reveal() {
+        assert_eq!(
+            render(Backend::Reveal),
+            "<!-- .slide: class=\"center middle\" -->\n# Page 1\nText\nNote:\nHello\n---\n## Page 2\n"
+        );
+    }
+
+    #[test]
+    fn marp() {
+        assert_eq!(
+            render(Backend::Marp),
+            "---\nmarp: true\ntitle: \"Chapter\"\n---\n\n\
+            <!-- _class: center middle -->\n# Page 1\nText\n<!--\nHello\n-->\n---\n## Page 2\n"
+        );
+        assert_eq!(
+            Backend::Marp
+                .render("Café \"Ünïcode\" C:\\", &[], &mut |_| Ok(String::new()))
+                .unwrap(),
+            "---\nmarp: true\ntitle: \"Café \\\"Ünïcode\\\" C:\\\\\"\n---\n\n"
+        );
+    }
+
+    #[test]
+    fn remark() {
+        assert_eq!(
+            render(Backend::Remark),
+            "class: center, middle\n# Page 1\nText\n???\nHello\n---\n## Page 2\n"
+        );
+    }
+}
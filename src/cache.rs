@@ -0,0 +1,170 @@
+//! A cache of rendered diagrams, shared between chapters and between builds
+//!
+//! Each rendered image is stored in a file named after a hash of everything
+//! which affects it - the diagram source, the renderer and its version, its
+//! options, and the image format.
+
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+
+use crate::{Book, Error};
+
+/// The cache folder used if `mdslides.toml` doesn't give one, relative to the
+/// book's root folder
+pub const DEFAULT_CACHE_DIR: &str = ".mdslides-cache";
+
+/// A folder of rendered diagrams
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+}
+
+impl Cache {
+    /// Use the given folder as a cache, creating it when first required.
+    pub fn new(dir: &Path) -> Cache {
+        Cache {
+            dir: dir.to_owned(),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+
+    /// The cache used by the given book.
+    pub fn for_book(book: &Book) -> Cache {
+        let dir = book
+            .config
+            .slides
+            .cache_dir
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_CACHE_DIR));
+        Cache::new(&book.root.join(dir))
+    }
+
+    /// Get the cached image for `key`, or call `render` and cache the result.
+    ///
+    /// Images which fail to render are not cached.
+    pub fn get_or_render(
+        &self,
+        key: &str,
+        extension: &str,
+        render: impl FnOnce() -> std::io::Result<Vec<u8>>,
+    ) -> std::io::Result<Vec<u8>> {
+        let path = self.dir.join(format!(
+            "{:016x}.{}",
+            crate::diagram::fnv1a(key.as_bytes()),
+            extension
+        ));
+        if let Ok(image) = std::fs::read(&path) {
+            log::debug!("Using cached diagram {}", path.display());
+            self.hits.set(self.hits.get() + 1);
+            return Ok(image);
+        }
+        self.misses.set(self.misses.get() + 1);
+        let image = render()?;
+        std::fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first, so an interrupted build can't
+        // leave a truncated image in the cache
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, &image)?;
+        std::fs::rename(&temp_path, &path)?;
+        Ok(image)
+    }
+
+    /// How many images were found in the cache, and how many had to be rendered
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits.get(), self.misses.get())
+    }
+
+    /// Delete every image in the cache, and the cache folder if that leaves
+    /// it empty.
+    ///
+    /// Only files named like the cache names them are deleted, so a
+    /// `cache_dir` which also holds other files (like the book itself) is
+    /// left alone.
+    pub fn clean(&self) -> Result<(), Error> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        log::info!("Removing cached diagrams from {}", self.dir.display());
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && is_cache_file(&entry.file_name()) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        // Leave the folder if anything else is in it
+        let _ = std::fs::remove_dir(&self.dir);
+        Ok(())
+    }
+}
+
+/// Is this the name of a file the cache writes, like `0123456789abcdef.svg`?
+fn is_cache_file(name: &std::ffi::OsStr) -> bool {
+    let Some((hash, extension)) = name.to_str().and_then(|name| name.split_once('.')) else {
+        return false;
+    };
+    hash.len() == 16
+        && hash.bytes().all(|b| b.is_ascii_hexdigit())
+        && ["svg", "png", "tmp"].contains(&extension)
+}
+
+/// Delete the diagram cache of an mdbook.
+///
+/// * `mdbook_path` - the location of the mdbook's `book.toml`. Assumed to be
+///   the current directory if not given.
+pub fn clean_cache(mdbook_path: Option<&Path>) -> Result<(), Error> {
+    let book = Book::load(mdbook_path)?;
+    Cache::for_book(&book).clean()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hits_and_misses() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(&dir.path().join("cache"));
+        let image = cache
+            .get_or_render("a", "svg", || Ok(b"<svg/>".to_vec()))
+            .unwrap();
+        assert_eq!(image, b"<svg/>");
+        let image = cache
+            .get_or_render("a", "svg", || panic!("should be cached"))
+            .unwrap();
+        assert_eq!(image, b"<svg/>");
+        assert!(cache
+            .get_or_render("b", "svg", || Err(std::io::Error::other("failed")))
+            .is_err());
+        assert_eq!(cache.stats(), (1, 2));
+
+        cache.clean().unwrap();
+        assert!(!dir.path().join("cache").exists());
+    }
+
+    #[test]
+    fn clean_leaves_other_files() {
+        // Like `cache_dir = "."`
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        cache
+            .get_or_render("a", "png", || Ok(b"png".to_vec()))
+            .unwrap();
+        std::fs::write(dir.path().join("book.toml"), "").unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/0123456789abcdef.svg"), "").unwrap();
+
+        cache.clean().unwrap();
+        let mut left: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["book.toml", "src"]);
+        assert!(dir.path().join("src/0123456789abcdef.svg").exists());
+    }
+}
@@ -0,0 +1,157 @@
+//! Checking a book for missing files and broken links
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::assets::{external_references, local_references, normalise};
+use crate::{chapter_file, diagram, links, percent_decode, slides, Book, Error};
+
+/// Settings for checking a book
+#[derive(Debug, Clone, Default)]
+pub struct CheckOptions {
+    /// Also check that external `http` and `https` URLs can be fetched
+    pub urls: bool,
+}
+
+/// Something wrong with a book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Where the problem is - a chapter's path in `SUMMARY.md`, or
+    /// `mdslides.toml`
+    pub location: String,
+    /// What the problem is
+    pub message: String,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Check an mdbook for missing chapter files, missing images and other
+/// files, links to anchors which don't exist, agendas including chapters
+/// which don't exist, and optionally external URLs which can't be fetched.
+///
+/// * `mdbook_path` - the location of the mdbook's `book.toml`. Assumed to be
+///   the current directory if not given.
+///
+/// Returns every problem found - an error is only returned if the book
+/// can't be checked at all.
+pub fn check(mdbook_path: Option<&Path>, options: &CheckOptions) -> Result<Vec<Problem>, Error> {
+    let book = Book::load(mdbook_path)?;
+    let diagrams = diagram::Registry::from_config(&book.config)?;
+    let diagram_languages = diagrams.languages();
+    let links = links::Links::for_book(&book, &diagram_languages)?;
+    let mut problems = Vec::new();
+    let mut urls = BTreeMap::new();
+
+    for (_title, path) in book.chapters() {
+        let mut problem = |message: String| {
+            problems.push(Problem {
+                location: path.to_owned(),
+                message,
+            })
+        };
+        let in_path = book.chapter_path(path);
+        let content = match std::fs::read_to_string(&in_path) {
+            Ok(content) => content,
+            Err(e) => {
+                problem(format!("can't read {}: {}", in_path.display(), e));
+                continue;
+            }
+        };
+        let content = match slides::front_matter(&content) {
+            Ok((_front_matter, content)) => content,
+            Err(e) => {
+                problem(format!("bad front matter: {}", e));
+                continue;
+            }
+        };
+
+        let chapter_file = chapter_file(path);
+        let chapter_dir = chapter_file.parent().unwrap_or(Path::new(""));
+        for reference in local_references(content) {
+            let exists = normalise(&chapter_dir.join(percent_decode(&reference)))
+                .map(|relative| book.src_dir.join(relative).exists())
+                .unwrap_or(false);
+            if !exists {
+                problem(format!("refers to {:?}, which doesn't exist", reference));
+            }
+        }
+        for message in links.broken_links(content, &in_path) {
+            problem(message);
+        }
+        if options.urls {
+            for url in external_references(content) {
+                urls.entry(url).or_insert_with(|| path.to_owned());
+            }
+        }
+    }
+
+    for dir in book.config.slides.static_dirs.iter() {
+        if !book.src_dir.join(dir).is_dir() {
+            problems.push(Problem {
+                location: "mdslides.toml".to_owned(),
+                message: format!("static folder {} doesn't exist", dir.display()),
+            });
+        }
+    }
+
+    let chapters: Vec<&str> = book
+        .chapters()
+        .map(|(_title, path)| path.trim_start_matches("./"))
+        .collect();
+    for agenda in book.config.agendas.iter() {
+        let sessions = agenda.days.iter().flat_map(|day| day.sessions.iter());
+        for path in sessions.flat_map(|session| session.chapters.iter()) {
+            if !chapters.contains(&path.trim_start_matches("./")) {
+                problems.push(Problem {
+                    location: "mdslides.toml".to_owned(),
+                    message: format!(
+                        "agenda {:?} includes {:?}, which isn't a chapter in SUMMARY.md",
+                        agenda.title, path
+                    ),
+                });
+            }
+        }
+    }
+
+    for (url, chapter) in urls {
+        if let Err(message) = check_url(&url) {
+            problems.push(Problem {
+                location: chapter,
+                message: format!("can't fetch {}: {}", url, message),
+            });
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Check that a URL can be fetched.
+#[cfg(feature = "check-urls")]
+fn check_url(url: &str) -> Result<(), String> {
+    log::info!("Checking {}", url);
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(10))
+        .build();
+    match agent.head(url).call() {
+        Ok(_) => Ok(()),
+        // Some servers don't support HEAD requests
+        Err(ureq::Error::Status(405, _)) => {
+            agent.get(url).call().map(|_| ()).map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Check that a URL can be fetched.
+#[cfg(not(feature = "check-urls"))]
+fn check_url(url: &str) -> Result<(), String> {
+    log::warn!(
+        "Not checking {}, as mdslides was built without the check-urls feature",
+        url
+    );
+    Ok(())
+}
@@ -0,0 +1,340 @@
+//! The `mdslides.toml` configuration file
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::lint::{Rule, Severity};
+use crate::{Backend, DiagramFormat, Error, TemplateSyntax};
+
+/// The contents of an `mdslides.toml` file
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Settings for generating slides
+    pub slides: SlidesConfig,
+    /// How to render diagrams in each language, from `[diagrams.<language>]`
+    pub diagrams: BTreeMap<String, DiagramConfig>,
+    /// Default settings for Graphviz diagrams
+    pub graphviz: GraphvizConfig,
+    /// Limits for `mdslides lint`
+    pub lint: LintConfig,
+    /// Rates for estimating how long slides take to present
+    pub timing: TimingConfig,
+    /// Course agendas to generate, from `[[agendas]]`
+    pub agendas: Vec<AgendaConfig>,
+}
+
+/// The `[slides]` table of an `mdslides.toml` file
+///
+/// Paths are relative to the book's root folder.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct SlidesConfig {
+    /// Where to write the slides
+    pub output_dir: Option<PathBuf>,
+    /// The template for the slide decks
+    pub template: Option<PathBuf>,
+    /// The template for the index file
+    pub index_template: Option<PathBuf>,
+    /// The slide framework to generate decks for
+    pub backend: Option<Backend>,
+    /// The syntax of the templates
+    pub template_syntax: Option<TemplateSyntax>,
+    /// The reveal.js theme - either one of [`THEMES`](crate::THEMES), or the URL of a CSS file
+    pub theme: Option<String>,
+    /// Where to find reveal.js - either a URL, a folder to copy into the
+    /// output, or `bundled` for the copy built into mdslides
+    pub reveal_js: Option<String>,
+    /// How to put rendered diagrams into the slides
+    pub diagram_format: Option<DiagramFormat>,
+    /// What to do if a diagram's renderer isn't installed
+    pub missing_renderer: Option<MissingRenderer>,
+    /// Where to keep rendered diagrams between builds
+    pub cache_dir: Option<PathBuf>,
+    /// Folders to copy into the output, relative to the book's `src` folder
+    pub static_dirs: Vec<PathBuf>,
+    /// Whether to write a search index of every slide, and a search widget
+    pub search: bool,
+    /// Chapter files to leave out of the slides
+    pub skip: Vec<String>,
+    /// Alternative templates for particular chapters, from `[[slides.templates]]`
+    pub templates: Vec<TemplateOverride>,
+}
+
+/// What to do when a diagram's renderer isn't installed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingRenderer {
+    /// Stop, with an error saying what to install
+    #[default]
+    Error,
+    /// Put the diagram's source into the slides, with a warning
+    Fallback,
+}
+
+/// Selects an alternative template for some chapters.
+///
+/// A chapter uses the template from the first override which matches it.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TemplateOverride {
+    /// Glob patterns for the chapter files, relative to the book's `src` folder
+    #[serde(default)]
+    pub chapters: Vec<String>,
+    /// Keys and values which must be present in the chapter's front matter
+    #[serde(default)]
+    pub front_matter: toml::Table,
+    /// The template file to use, relative to the book's root folder
+    pub template: PathBuf,
+}
+
+/// How to render diagrams written in one particular language.
+///
+/// Give either a built-in `renderer`, or a `command`.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct DiagramConfig {
+    /// A built-in renderer - `graphviz`, `mermaid`, `svgbob` or `builtin-graphviz`
+    pub renderer: Option<String>,
+    /// A program and its arguments, which reads the diagram on stdin and
+    /// writes SVG to stdout
+    pub command: Vec<String>,
+}
+
+/// The `[graphviz]` table of an `mdslides.toml` file
+///
+/// These can be overridden by attributes on each diagram's code fence, or
+/// by attributes set inside the graph.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct GraphvizConfig {
+    /// The layout engine, such as `dot`, `neato` or `circo`
+    pub engine: Option<String>,
+    /// The resolution, which affects PNG images and the size of SVG images
+    pub dpi: Option<u32>,
+    /// The background colour
+    pub background: Option<String>,
+    /// Default attributes for graphs
+    pub graph: toml::Table,
+    /// Default attributes for nodes
+    pub node: toml::Table,
+    /// Default attributes for edges
+    pub edge: toml::Table,
+}
+
+/// The `[lint]` table of an `mdslides.toml` file
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct LintConfig {
+    /// The most lines of text (including code) a slide should have
+    pub max_lines: usize,
+    /// The most bullet points a slide should have
+    pub max_bullets: usize,
+    /// The most lines a code block should have
+    pub max_code_lines: usize,
+    /// The most characters a line of text should have
+    pub max_line_length: usize,
+    /// The most characters a line of code should have
+    pub max_code_width: usize,
+    /// The severity of each rule, if not [`Severity::Warn`]
+    pub rules: BTreeMap<Rule, Severity>,
+}
+
+impl Default for LintConfig {
+    fn default() -> LintConfig {
+        LintConfig {
+            max_lines: 20,
+            max_bullets: 7,
+            max_code_lines: 20,
+            max_line_length: 100,
+            max_code_width: 70,
+            rules: BTreeMap::new(),
+        }
+    }
+}
+
+impl LintConfig {
+    /// How seriously to take a rule
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.rules.get(&rule).copied().unwrap_or_default()
+    }
+}
+
+/// A course agenda, which is generated as its own page.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AgendaConfig {
+    /// The title of the agenda page
+    pub title: String,
+    /// The file to write, relative to the output directory
+    pub file: PathBuf,
+    /// The template for the page, relative to the book's root folder
+    #[serde(default)]
+    pub template: Option<PathBuf>,
+    /// The days of the course, from `[[agendas.days]]`
+    #[serde(default)]
+    pub days: Vec<AgendaDayConfig>,
+}
+
+/// A day of a course agenda
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AgendaDayConfig {
+    /// The title of the day, like `Day 1`
+    pub title: String,
+    /// When the day starts, like `09:00`
+    #[serde(default)]
+    pub start: Option<String>,
+    /// The sessions and breaks, in order, from `[[agendas.days.sessions]]`
+    #[serde(default)]
+    pub sessions: Vec<AgendaSessionConfig>,
+}
+
+/// A session, or a break, in a day of a course agenda
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AgendaSessionConfig {
+    /// The title of the session
+    pub title: String,
+    /// When the session starts, like `13:00`, if not straight after the
+    /// session before
+    #[serde(default)]
+    pub start: Option<String>,
+    /// The chapters covered, by their paths in `SUMMARY.md`
+    #[serde(default)]
+    pub chapters: Vec<String>,
+    /// If this is a break, how many minutes it lasts
+    #[serde(default, rename = "break")]
+    pub break_minutes: Option<u32>,
+}
+
+/// The `[timing]` table of an `mdslides.toml` file
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct TimingConfig {
+    /// Time for every slide, however little is on it
+    pub seconds_per_slide: u32,
+    /// How quickly the words on a slide are covered
+    pub words_per_minute: u32,
+    /// Time for each line of code
+    pub seconds_per_code_line: u32,
+    /// Time for each diagram
+    pub seconds_per_diagram: u32,
+}
+
+impl Default for TimingConfig {
+    fn default() -> TimingConfig {
+        TimingConfig {
+            seconds_per_slide: 30,
+            words_per_minute: 60,
+            seconds_per_code_line: 5,
+            seconds_per_diagram: 60,
+        }
+    }
+}
+
+impl Config {
+    /// Load an `mdslides.toml` file.
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let config_src = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&config_src)?;
+        for template_override in config.slides.templates.iter() {
+            for pattern in template_override.chapters.iter() {
+                glob::Pattern::new(pattern)?;
+            }
+        }
+        for agenda in config.agendas.iter() {
+            if crate::assets::normalise(&agenda.file).is_none() {
+                return Err(Error::BadAgenda(format!(
+                    "the file {:?} for {:?} is outside the output directory",
+                    agenda.file, agenda.title
+                )));
+            }
+            let days = agenda.days.iter();
+            let starts = days
+                .flat_map(|day| {
+                    std::iter::once(&day.start).chain(day.sessions.iter().map(|s| &s.start))
+                })
+                .flatten();
+            for start in starts {
+                if crate::agenda::parse_time_of_day(start).is_none() {
+                    return Err(Error::BadAgenda(format!(
+                        "{:?} in {:?} is not a time like 09:30",
+                        start, agenda.title
+                    )));
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl TemplateOverride {
+    /// Does this override apply to the chapter at `path`, with the given front matter?
+    ///
+    /// Every glob and front matter value given must match. An override with
+    /// neither matches nothing.
+    pub fn matches(&self, path: &str, front_matter: &toml::Table) -> bool {
+        if self.chapters.is_empty() && self.front_matter.is_empty() {
+            return false;
+        }
+        let path = path.trim_start_matches("./");
+        let path_matches = self.chapters.is_empty()
+            || self.chapters.iter().any(|pattern| {
+                glob::Pattern::new(pattern.trim_start_matches("./"))
+                    .map(|pattern| pattern.matches(path))
+                    .unwrap_or(false)
+            });
+        let front_matter_matches = self
+            .front_matter
+            .iter()
+            .all(|(key, value)| front_matter.get(key) == Some(value));
+        path_matches && front_matter_matches
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn template_overrides() {
+        let config: Config = toml::from_str(
+            r#"
+            [[slides.templates]]
+            chapters = ["exercises/*.md"]
+            template = "exercise.html"
+
+            [[slides.templates]]
+            front_matter = { layout = "title" }
+            template = "title.html"
+            "#,
+        )
+        .unwrap();
+        let [exercise, title] = &config.slides.templates[..] else {
+            panic!("Wrong number of overrides");
+        };
+        let empty = toml::Table::new();
+        let layout: toml::Table = toml::from_str("layout = \"title\"").unwrap();
+        assert!(exercise.matches("./exercises/fizzbuzz.md", &empty));
+        assert!(!exercise.matches("./intro.md", &empty));
+        assert!(title.matches("./intro.md", &layout));
+        assert!(!title.matches("./intro.md", &empty));
+    }
+
+    #[test]
+    fn agenda_outside_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mdslides.toml");
+        for file in ["../agenda.html", "/tmp/agenda.html"] {
+            std::fs::write(
+                &path,
+                format!("[[agendas]]\ntitle = \"Course\"\nfile = \"{}\"\n", file),
+            )
+            .unwrap();
+            assert!(matches!(Config::load(&path), Err(Error::BadAgenda(_))));
+        }
+        std::fs::write(
+            &path,
+            "[[agendas]]\ntitle = \"Course\"\nfile = \"course/agenda.html\"\n",
+        )
+        .unwrap();
+        Config::load(&path).unwrap();
+    }
+}
@@ -0,0 +1,954 @@
+//! Rendering diagrams into images
+//!
+//! Code blocks such as ```` ```dot process ```` are collected as
+//! [`Diagram`](crate::slides::Diagram)s, and rendered into SVG by the
+//! [`DiagramRenderer`] registered for the language of the code block.
+//!
+//! The rendered diagrams are either put into the slides, or written as files
+//! in an `assets` folder, depending on the [`DiagramFormat`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::cache::Cache;
+use crate::config::{Config, GraphvizConfig, MissingRenderer};
+use crate::slides::Diagram;
+use crate::template::escape_html;
+use crate::Error;
+
+/// The folder, inside the output directory, where diagram files are written
+pub const ASSETS_DIR: &str = "assets";
+
+/// How rendered diagrams are put into the slides
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagramFormat {
+    /// SVG, inside the slides
+    #[default]
+    Inline,
+    /// SVG files in the `assets` folder
+    Svg,
+    /// PNG files in the `assets` folder
+    Png,
+}
+
+/// Something which can turn the source code of a diagram into an image
+pub trait DiagramRenderer {
+    /// A name for this renderer, for log messages
+    fn name(&self) -> &str;
+
+    /// Render the diagram into an SVG image
+    fn render(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>>;
+
+    /// Check whether this renderer can be used, e.g. whether the program it
+    /// runs is installed.
+    ///
+    /// Returns a message saying what is missing, and how to get it, if not.
+    fn check_available(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Another renderer to use for the same diagrams, if this one isn't
+    /// available.
+    fn fallback(&self) -> Option<Box<dyn DiagramRenderer>> {
+        None
+    }
+
+    /// Everything other than the diagram's source which affects the image,
+    /// such as the renderer's version and options.
+    ///
+    /// Rendered images are cached using this and the source as the key.
+    fn cache_key(&self, diagram: &Diagram) -> String {
+        format!("{} {:?}", self.name(), diagram.attributes)
+    }
+
+    /// Check that whatever [`DiagramRenderer::render_png`] needs, beyond what
+    /// [`DiagramRenderer::check_available`] checks, is installed.
+    ///
+    /// By default this checks for `rsvg-convert`.
+    fn check_png_available(&self) -> Result<(), String> {
+        if find_program("rsvg-convert").is_some() {
+            Ok(())
+        } else {
+            Err("the `rsvg-convert` program, used to make PNG images, was not found - install it from librsvg".to_owned())
+        }
+    }
+
+    /// Render the diagram into a PNG image.
+    ///
+    /// By default this converts the SVG image with `rsvg-convert`.
+    fn render_png(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+        let svg = self.render(diagram)?;
+        run_with_stdin("rsvg-convert", &["--format=png".to_owned()], &svg)
+    }
+}
+
+/// The renderers to use for each diagram language, and where to put the
+/// rendered diagrams
+pub struct Registry {
+    renderers: BTreeMap<String, Box<dyn DiagramRenderer>>,
+    format: DiagramFormat,
+    output_dir: PathBuf,
+    cache: Option<Cache>,
+    /// Languages whose renderer isn't available, so we show their source instead
+    unavailable: BTreeSet<String>,
+}
+
+impl Default for Registry {
+    /// A registry containing the built-in renderers, which puts diagrams
+    /// inside the slides
+    fn default() -> Registry {
+        let mut registry = Registry {
+            renderers: BTreeMap::new(),
+            format: DiagramFormat::Inline,
+            output_dir: PathBuf::new(),
+            cache: None,
+            unavailable: BTreeSet::new(),
+        };
+        for language in ["dot", "mermaid", "svgbob", "bob"] {
+            if let Some(renderer) = builtin(language, &GraphvizConfig::default()) {
+                registry.register(language, renderer);
+            }
+        }
+        registry
+    }
+}
+
+impl Registry {
+    /// Create a registry with the built-in renderers, plus those in the
+    /// `[diagrams]` table of `mdslides.toml`.
+    ///
+    /// Graphviz diagrams use the settings in the `[graphviz]` table.
+    pub fn from_config(config: &Config) -> Result<Registry, Error> {
+        let mut registry = Registry::default();
+        registry.register("dot", Box::new(Graphviz::new(&config.graphviz)));
+        for (language, diagram_config) in config.diagrams.iter() {
+            let renderer: Box<dyn DiagramRenderer> =
+                match (&diagram_config.renderer, &diagram_config.command[..]) {
+                    (None, [program, args @ ..]) => {
+                        Box::new(CommandRenderer::new(program, args.to_vec()))
+                    }
+                    (Some(name), []) => builtin(name, &config.graphviz)
+                        .ok_or_else(|| Error::UnknownRenderer(name.clone()))?,
+                    _ => return Err(Error::BadDiagramConfig(language.clone())),
+                };
+            registry.register(language, renderer);
+        }
+        Ok(registry)
+    }
+
+    /// Use the given renderer for diagrams in the given language.
+    pub fn register(&mut self, language: &str, renderer: Box<dyn DiagramRenderer>) {
+        self.renderers.insert(language.to_owned(), renderer);
+    }
+
+    /// Get the renderer for diagrams in the given language.
+    pub fn get(&self, language: &str) -> Option<&dyn DiagramRenderer> {
+        self.renderers
+            .get(language)
+            .map(|renderer| renderer.as_ref())
+    }
+
+    /// The languages we have a renderer for.
+    pub fn languages(&self) -> Vec<&str> {
+        self.renderers.keys().map(String::as_str).collect()
+    }
+
+    /// Put rendered diagrams into the slides in the given format.
+    ///
+    /// Diagram files are written to the `assets` folder inside `output_dir`.
+    pub fn set_format(&mut self, format: DiagramFormat, output_dir: &Path) {
+        self.format = format;
+        self.output_dir = output_dir.to_owned();
+    }
+
+    /// Keep rendered diagrams in the given cache.
+    pub fn set_cache(&mut self, cache: Cache) {
+        self.cache = Some(cache);
+    }
+
+    /// The cache being used, if any
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
+    }
+
+    /// Check that the renderers for the given languages can be used.
+    ///
+    /// If one can't be used, either return an error, or remember to put the
+    /// source of those diagrams into the slides instead, with a warning.
+    pub fn check_available<'a>(
+        &mut self,
+        languages: impl IntoIterator<Item = &'a str>,
+        missing_renderer: MissingRenderer,
+    ) -> Result<(), Error> {
+        for language in languages {
+            let Some(renderer) = self.get(language) else {
+                continue;
+            };
+            if let Err(message) = self.usable(renderer) {
+                if let Some(fallback) = renderer.fallback() {
+                    if self.usable(fallback.as_ref()).is_ok() {
+                        log::info!(
+                            "Using {} for {} diagrams, because {}",
+                            fallback.name(),
+                            language,
+                            message
+                        );
+                        self.register(language, fallback);
+                        continue;
+                    }
+                }
+                match missing_renderer {
+                    MissingRenderer::Error => {
+                        return Err(Error::RendererNotAvailable(language.to_owned(), message))
+                    }
+                    MissingRenderer::Fallback => {
+                        log::warn!(
+                            "Showing the source of {} diagrams, because {}",
+                            language,
+                            message
+                        );
+                        self.unavailable.insert(language.to_owned());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a renderer can make diagrams in our format.
+    fn usable(&self, renderer: &dyn DiagramRenderer) -> Result<(), String> {
+        renderer.check_available()?;
+        if self.format == DiagramFormat::Png {
+            renderer.check_png_available()?;
+        }
+        Ok(())
+    }
+
+    /// Render a diagram, and return the HTML to put in its place in the deck
+    /// at `deck_path`.
+    ///
+    /// Unless the format is [`DiagramFormat::Inline`], the image is written to
+    /// the `assets` folder, named after a hash of its contents, and the HTML
+    /// refers to it with a relative URL.
+    pub fn to_html(&self, diagram: &Diagram, deck_path: &Path) -> std::io::Result<String> {
+        let renderer = self.get(&diagram.language).ok_or_else(|| {
+            std::io::Error::other(format!("No renderer for {:?}", diagram.language))
+        })?;
+        if self.unavailable.contains(&diagram.language) {
+            return Ok(fallback(diagram, renderer.name()));
+        }
+        let extension = match self.format {
+            DiagramFormat::Inline | DiagramFormat::Svg => "svg",
+            DiagramFormat::Png => "png",
+        };
+        let render = || {
+            log::info!(
+                "Calling {} to render diagram in {}",
+                renderer.name(),
+                deck_path.display()
+            );
+            match self.format {
+                DiagramFormat::Inline | DiagramFormat::Svg => renderer.render(diagram),
+                DiagramFormat::Png => renderer.render_png(diagram),
+            }
+        };
+        let image = match self.cache.as_ref() {
+            Some(cache) => {
+                let key = format!(
+                    "mdslides {}\n{}\n{}\n{}",
+                    env!("CARGO_PKG_VERSION"),
+                    renderer.cache_key(diagram),
+                    extension,
+                    diagram.source
+                );
+                cache.get_or_render(&key, extension, render)?
+            }
+            None => render()?,
+        };
+        if self.format == DiagramFormat::Inline {
+            return Ok(format!(
+                "<figure>\n{}</figure>\n",
+                String::from_utf8_lossy(&image)
+            ));
+        }
+        let filename = format!("{:016x}.{}", fnv1a(&image), extension);
+        let assets_dir = self.output_dir.join(ASSETS_DIR);
+        std::fs::create_dir_all(&assets_dir)?;
+        std::fs::write(assets_dir.join(&filename), &image)?;
+
+        // Decks in sub-folders need to go up to the output directory first
+        let depth = deck_path
+            .strip_prefix(&self.output_dir)
+            .ok()
+            .and_then(Path::parent)
+            .map(|parent| {
+                parent
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .count()
+            })
+            .unwrap_or(0);
+        let url = format!("{}{}/{}", "../".repeat(depth), ASSETS_DIR, filename);
+        Ok(format!(
+            "<figure>\n<img src=\"{}\" alt=\"{}\">\n</figure>\n",
+            url,
+            escape_html(&diagram.alt_text())
+        ))
+    }
+}
+
+/// The Markdown to show in place of a diagram we can't render - a warning,
+/// and the diagram's source as a normal code block.
+fn fallback(diagram: &Diagram, renderer: &str) -> String {
+    format!(
+        "> **Warning:** this diagram was not rendered, because {} is not installed\n\n```{}\n{}```\n",
+        renderer, diagram.language, diagram.source
+    )
+}
+
+/// Look for a program in the folders on the `PATH`, and return where it is.
+fn find_program(program: &str) -> Option<PathBuf> {
+    let program = Path::new(program);
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_owned());
+    }
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths).find_map(|dir| {
+        let path = dir.join(program);
+        if path.is_file() {
+            Some(path)
+        } else if cfg!(windows) && path.with_extension("exe").is_file() {
+            Some(path.with_extension("exe"))
+        } else {
+            None
+        }
+    })
+}
+
+/// Identify the installed copy of a program, by where it is, when it was
+/// last changed and how big it is, so that upgrading it changes the key
+/// its diagrams are cached with.
+fn program_identity(program: &str) -> String {
+    let Some(path) = find_program(program) else {
+        return format!("{} not found", program);
+    };
+    // Follow symlinks, like the ones npm installs, to the real program
+    let path = std::fs::canonicalize(&path).unwrap_or(path);
+    let (modified, size) = match std::fs::metadata(&path) {
+        Ok(metadata) => (
+            metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos())
+                .unwrap_or_default(),
+            metadata.len(),
+        ),
+        Err(_) => (0, 0),
+    };
+    format!("{} {} {}", path.display(), modified, size)
+}
+
+/// The 64-bit FNV-1a hash of some data.
+///
+/// Unlike the standard library's hashers, this is guaranteed not to change
+/// between Rust versions, so file names stay the same from build to build.
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Ask a program for its version, by running it with the given argument.
+///
+/// Some programs print their version to stderr, so we use both outputs.
+fn program_version(program: &str, arg: &str) -> String {
+    match Command::new(program).arg(arg).output() {
+        Ok(output) => format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout).trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(_) => "unknown version".to_owned(),
+    }
+}
+
+/// Run a program, giving it `input` on stdin, and return its stdout.
+fn run_with_stdin(program: &str, args: &[String], input: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin was piped");
+    let input = input.to_owned();
+    // Write from another thread, in case the program fills its stdout
+    // pipe before it has read all of its input
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let result = child.wait_with_output()?;
+    // A program which fails may not read all of its input, so check how it
+    // exited before checking that the input was written
+    let written = writer.join().expect("stdin writer panicked");
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        let message = if stderr.trim().is_empty() {
+            format!("`{}` failed with {}", program, result.status)
+        } else {
+            stderr.trim_end().to_owned()
+        };
+        return Err(std::io::Error::other(message));
+    }
+    written?;
+    Ok(result.stdout)
+}
+
+/// Get a built-in renderer by name.
+fn builtin(name: &str, graphviz: &GraphvizConfig) -> Option<Box<dyn DiagramRenderer>> {
+    match name {
+        "dot" | "graphviz" => Some(Box::new(Graphviz::new(graphviz))),
+        "mermaid" => Some(Box::new(Mermaid)),
+        #[cfg(feature = "svgbob")]
+        "svgbob" | "bob" => Some(Box::new(Svgbob)),
+        #[cfg(feature = "builtin-graphviz")]
+        "builtin-graphviz" => Some(Box::new(BuiltinGraphviz)),
+        _ => None,
+    }
+}
+
+/// Renders Graphviz diagrams, using the `dot` program
+pub struct Graphviz {
+    /// The default settings, which code fence attributes can override
+    config: GraphvizConfig,
+    /// The output of `dot -V`, once we've asked for it
+    version: OnceLock<String>,
+}
+
+impl Graphviz {
+    /// Create a Graphviz renderer with the given default settings.
+    pub fn new(config: &GraphvizConfig) -> Graphviz {
+        Graphviz {
+            config: config.clone(),
+            version: OnceLock::new(),
+        }
+    }
+
+    /// The command line arguments for `dot`, other than the output format.
+    ///
+    /// The defaults from the config come first, then the code fence's
+    /// `key=value` attributes: `engine` selects the layout engine,
+    /// `background` sets `bgcolor`, and anything else (like `scale` or
+    /// `dpi`) sets a graph attribute. Attributes given inside the graph
+    /// itself take priority over all of these.
+    fn args(&self, diagram: &Diagram) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut engine = self.config.engine.clone();
+        if let Some(dpi) = self.config.dpi {
+            args.push(format!("-Gdpi={}", dpi));
+        }
+        if let Some(background) = self.config.background.as_ref() {
+            args.push(format!("-Gbgcolor={}", background));
+        }
+        for (flag, attributes) in [
+            ("-G", &self.config.graph),
+            ("-N", &self.config.node),
+            ("-E", &self.config.edge),
+        ] {
+            for (key, value) in attributes.iter() {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    other => other.to_string(),
+                };
+                args.push(format!("{}{}={}", flag, key, value));
+            }
+        }
+        for (key, value) in diagram
+            .attributes
+            .iter()
+            .filter_map(|attribute| attribute.split_once('='))
+        {
+            match key {
+                "alt" => {}
+                "engine" => engine = Some(value.to_owned()),
+                "background" => args.push(format!("-Gbgcolor={}", value)),
+                _ => args.push(format!("-G{}={}", key, value)),
+            }
+        }
+        if let Some(engine) = engine {
+            args.push(format!("-K{}", engine));
+        }
+        args
+    }
+
+    /// Run `dot`, producing the given output format.
+    fn exec(
+        &self,
+        diagram: &Diagram,
+        format: graphviz_rust::cmd::Format,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut args: Vec<graphviz_rust::cmd::CommandArg> = self
+            .args(diagram)
+            .into_iter()
+            .map(graphviz_rust::cmd::CommandArg::Custom)
+            .collect();
+        args.push(graphviz_rust::cmd::CommandArg::Format(format));
+        graphviz_rust::exec_dot(diagram.source.clone(), args)
+    }
+}
+
+impl DiagramRenderer for Graphviz {
+    fn name(&self) -> &str {
+        "graphviz"
+    }
+
+    fn check_available(&self) -> Result<(), String> {
+        if find_program("dot").is_some() {
+            Ok(())
+        } else {
+            Err("the `dot` program from Graphviz was not found - install Graphviz from https://graphviz.org/download/".to_owned())
+        }
+    }
+
+    #[cfg(feature = "builtin-graphviz")]
+    fn fallback(&self) -> Option<Box<dyn DiagramRenderer>> {
+        Some(Box::new(BuiltinGraphviz))
+    }
+
+    fn cache_key(&self, diagram: &Diagram) -> String {
+        let version = self.version.get_or_init(|| program_version("dot", "-V"));
+        format!("{} {:?}", version, self.args(diagram))
+    }
+
+    fn render(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+        self.exec(diagram, graphviz_rust::cmd::Format::Svg)
+    }
+
+    fn check_png_available(&self) -> Result<(), String> {
+        // `dot` makes PNGs itself
+        Ok(())
+    }
+
+    fn render_png(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+        self.exec(diagram, graphviz_rust::cmd::Format::Png)
+    }
+}
+
+/// Renders Graphviz diagrams with the pure-Rust `layout-rs` library, so
+/// Graphviz doesn't need to be installed.
+///
+/// This understands less of the DOT language than Graphviz does, and
+/// ignores the layout engine and other attributes from the code fence and
+/// `[graphviz]` table.
+#[cfg(feature = "builtin-graphviz")]
+pub struct BuiltinGraphviz;
+
+/// The version of `layout-rs` we build with, for the cache key. Keep this in
+/// step with `Cargo.toml`.
+#[cfg(feature = "builtin-graphviz")]
+const LAYOUT_RS_VERSION: &str = "0.1.2";
+
+#[cfg(feature = "builtin-graphviz")]
+impl DiagramRenderer for BuiltinGraphviz {
+    fn name(&self) -> &str {
+        "builtin-graphviz"
+    }
+
+    fn cache_key(&self, diagram: &Diagram) -> String {
+        format!(
+            "{} {} {:?}",
+            self.name(),
+            LAYOUT_RS_VERSION,
+            diagram.attributes
+        )
+    }
+
+    fn render(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+        let mut parser = layout::gv::DotParser::new(&diagram.source);
+        let graph = parser.process().map_err(std::io::Error::other)?;
+        // The layout code panics on some graphs it doesn't support
+        let svg = std::panic::catch_unwind(|| {
+            let mut builder = layout::gv::GraphBuilder::new();
+            builder.visit_graph(&graph);
+            let mut visual_graph = builder.get();
+            let mut writer = layout::backends::svg::SVGWriter::new();
+            visual_graph.do_it(false, false, false, &mut writer);
+            writer.finalize()
+        })
+        .map_err(|_| std::io::Error::other("the built-in Graphviz layout failed"))?;
+        let mut svg = svg.into_bytes();
+        if !svg.ends_with(b"\n") {
+            svg.push(b'\n');
+        }
+        Ok(svg)
+    }
+}
+
+/// Renders Mermaid diagrams, using the `mmdc` program from `mermaid-cli`
+pub struct Mermaid;
+
+impl DiagramRenderer for Mermaid {
+    fn name(&self) -> &str {
+        "mermaid"
+    }
+
+    fn check_available(&self) -> Result<(), String> {
+        if find_program("mmdc").is_some() {
+            Ok(())
+        } else {
+            Err("the `mmdc` program was not found - install it with `npm install -g @mermaid-js/mermaid-cli`".to_owned())
+        }
+    }
+
+    fn cache_key(&self, diagram: &Diagram) -> String {
+        static VERSION: OnceLock<String> = OnceLock::new();
+        let version = VERSION.get_or_init(|| {
+            format!(
+                "{} {}",
+                program_version("mmdc", "--version"),
+                program_identity("mmdc")
+            )
+        });
+        format!("mermaid {} {:?}", version, diagram.attributes)
+    }
+
+    fn render(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+        let mut svg = mmdc(&diagram.source, "svg")?;
+        if !svg.ends_with(b"\n") {
+            svg.push(b'\n');
+        }
+        Ok(svg)
+    }
+
+    fn check_png_available(&self) -> Result<(), String> {
+        // `mmdc` makes PNGs itself
+        Ok(())
+    }
+
+    fn render_png(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+        mmdc(&diagram.source, "png")
+    }
+}
+
+/// Render a Mermaid diagram into an image with the given file extension.
+fn mmdc(source: &str, extension: &str) -> std::io::Result<Vec<u8>> {
+    // mmdc works with files, not stdin and stdout
+    let dir = tempfile::tempdir()?;
+    let input = dir.path().join("diagram.mmd");
+    let output = dir.path().join(format!("diagram.{}", extension));
+    std::fs::write(&input, source)?;
+    let result = Command::new("mmdc")
+        .arg("--quiet")
+        .arg("--input")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .output()?;
+    if !result.status.success() {
+        return Err(std::io::Error::other(
+            String::from_utf8_lossy(&result.stderr).into_owned(),
+        ));
+    }
+    std::fs::read(&output)
+}
+
+/// Renders ASCII-art diagrams, using the `svgbob` library
+#[cfg(feature = "svgbob")]
+pub struct Svgbob;
+
+#[cfg(feature = "svgbob")]
+impl DiagramRenderer for Svgbob {
+    fn name(&self) -> &str {
+        "svgbob"
+    }
+
+    fn render(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+        let mut svg = svgbob::to_svg(&diagram.source);
+        svg.push('\n');
+        Ok(svg.into_bytes())
+    }
+}
+
+/// Renders diagrams with some other program, which reads the diagram source
+/// code on stdin and writes SVG to stdout
+pub struct CommandRenderer {
+    /// The program to run
+    program: String,
+    /// The arguments to give it
+    args: Vec<String>,
+    /// Which copy of the program is installed, once we've looked
+    identity: OnceLock<String>,
+}
+
+impl CommandRenderer {
+    /// Create a renderer which runs `program` with the given arguments.
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> CommandRenderer {
+        CommandRenderer {
+            program: program.into(),
+            args,
+            identity: OnceLock::new(),
+        }
+    }
+}
+
+impl DiagramRenderer for CommandRenderer {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn check_available(&self) -> Result<(), String> {
+        if find_program(&self.program).is_some() {
+            Ok(())
+        } else {
+            Err(format!("the `{}` program was not found", self.program))
+        }
+    }
+
+    fn cache_key(&self, diagram: &Diagram) -> String {
+        // We don't know how to ask an arbitrary program for its version
+        let identity = self
+            .identity
+            .get_or_init(|| program_identity(&self.program));
+        format!(
+            "{} {} {:?} {:?}",
+            self.program, identity, self.args, diagram.attributes
+        )
+    }
+
+    fn render(&self, diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+        run_with_stdin(&self.program, &self.args, diagram.source.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registry_from_config() {
+        let config: crate::Config = toml::from_str(
+            r#"
+            [diagrams.upper]
+            command = ["tr", "a-z", "A-Z"]
+
+            [diagrams.dot]
+            renderer = "mermaid"
+            "#,
+        )
+        .unwrap();
+        let registry = Registry::from_config(&config).unwrap();
+        assert_eq!(registry.get("dot").unwrap().name(), "mermaid");
+        assert!(registry.languages().contains(&"upper"));
+        if cfg!(unix) {
+            let diagram = Diagram {
+                language: "upper".to_owned(),
+                attributes: Vec::new(),
+                source: "svg".to_owned(),
+            };
+            let output = registry.get("upper").unwrap().render(&diagram).unwrap();
+            assert_eq!(output, b"SVG");
+        }
+    }
+
+    #[test]
+    fn bad_config() {
+        let config: crate::Config = toml::from_str(
+            r#"
+            [diagrams.foo]
+            renderer = "nonsense"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            Registry::from_config(&config),
+            Err(Error::UnknownRenderer(_))
+        ));
+    }
+
+    #[test]
+    fn graphviz_args() {
+        let config: crate::Config = toml::from_str(
+            r#"
+            [graphviz]
+            engine = "neato"
+            dpi = 150
+            background = "transparent"
+            node = { fontname = "Fira Sans", fontsize = 20 }
+            "#,
+        )
+        .unwrap();
+        let graphviz = Graphviz::new(&config.graphviz);
+        let diagram = Diagram {
+            language: "dot".to_owned(),
+            attributes: vec![
+                "process".to_owned(),
+                "engine=circo".to_owned(),
+                "scale=1.5".to_owned(),
+                "alt=A graph".to_owned(),
+            ],
+            source: "digraph {}\n".to_owned(),
+        };
+        assert_eq!(
+            graphviz.args(&diagram),
+            [
+                "-Gdpi=150",
+                "-Gbgcolor=transparent",
+                "-Nfontname=Fira Sans",
+                "-Nfontsize=20",
+                "-Gscale=1.5",
+                "-Kcirco",
+            ]
+        );
+    }
+
+    /// A renderer which is installed, but can't make PNGs
+    struct SvgOnly;
+
+    impl DiagramRenderer for SvgOnly {
+        fn name(&self) -> &str {
+            "svg-only"
+        }
+
+        fn check_png_available(&self) -> Result<(), String> {
+            Err("no PNG converter".to_owned())
+        }
+
+        fn render(&self, _diagram: &Diagram) -> std::io::Result<Vec<u8>> {
+            Ok(b"<svg/>".to_vec())
+        }
+    }
+
+    #[test]
+    fn missing_png_converter() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let mut registry = Registry::default();
+        registry.register("svg", Box::new(SvgOnly));
+        registry
+            .check_available(["svg"], MissingRenderer::Error)
+            .unwrap();
+
+        registry.set_format(DiagramFormat::Png, output_dir.path());
+        assert!(matches!(
+            registry.check_available(["svg"], MissingRenderer::Error),
+            Err(Error::RendererNotAvailable(_, message)) if message == "no PNG converter"
+        ));
+        registry
+            .check_available(["svg"], MissingRenderer::Fallback)
+            .unwrap();
+        let diagram = Diagram {
+            language: "svg".to_owned(),
+            attributes: vec!["process".to_owned()],
+            source: "<svg/>\n".to_owned(),
+        };
+        let markdown = registry.to_html(&diagram, Path::new("x.html")).unwrap();
+        assert!(markdown.starts_with("> **Warning:** this diagram was not rendered"));
+    }
+
+    #[test]
+    fn missing_renderer() {
+        let mut registry = Registry::default();
+        registry.register(
+            "nope",
+            Box::new(CommandRenderer::new("mdslides-no-such-program", Vec::new())),
+        );
+        assert!(matches!(
+            registry.check_available(["nope"], MissingRenderer::Error),
+            Err(Error::RendererNotAvailable(..))
+        ));
+        registry
+            .check_available(["nope"], MissingRenderer::Fallback)
+            .unwrap();
+        let diagram = Diagram {
+            language: "nope".to_owned(),
+            attributes: vec!["process".to_owned()],
+            source: "a -> b\n".to_owned(),
+        };
+        let markdown = registry.to_html(&diagram, Path::new("x.html")).unwrap();
+        assert_eq!(
+            markdown,
+            "> **Warning:** this diagram was not rendered, because mdslides-no-such-program is not installed\n\n```nope\na -> b\n```\n"
+        );
+    }
+
+    #[test]
+    fn command_cache_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("render-diagram");
+        let program = program.to_str().unwrap();
+        let diagram = Diagram {
+            language: "custom".to_owned(),
+            attributes: vec!["process".to_owned()],
+            source: "a -> b\n".to_owned(),
+        };
+        let key = || CommandRenderer::new(program, Vec::new()).cache_key(&diagram);
+        std::fs::write(program, "version 1\n").unwrap();
+        let old_key = key();
+        assert_eq!(old_key, key());
+        // Upgrading the program changes the key
+        std::fs::write(program, "version 1.1\n").unwrap();
+        assert_ne!(old_key, key());
+    }
+
+    #[cfg(feature = "builtin-graphviz")]
+    #[test]
+    fn builtin_graphviz() {
+        let diagram = Diagram {
+            language: "dot".to_owned(),
+            attributes: vec!["process".to_owned()],
+            source: "digraph {\n  Build -> Test -> Deploy;\n  Build -> Docs;\n}\n".to_owned(),
+        };
+        let svg = String::from_utf8(BuiltinGraphviz.render(&diagram).unwrap()).unwrap();
+        // Compare the structure, rather than exact coordinates
+        assert!(svg.starts_with("<?xml"));
+        assert_eq!(svg.matches("<svg").count(), 1);
+        assert!(svg.trim_end().ends_with("</svg>"));
+        for label in ["Build", "Test", "Deploy", "Docs"] {
+            assert!(svg.contains(&format!(">{}<", label)), "{} missing", label);
+        }
+        assert_eq!(svg.matches("<path id=\"arrow").count(), 3);
+
+        let bad = Diagram {
+            source: "digraph {".to_owned(),
+            ..diagram
+        };
+        assert!(BuiltinGraphviz.render(&bad).is_err());
+
+        // The cache key changes when layout-rs is upgraded
+        let manifest = include_str!("../Cargo.toml");
+        let requirement = format!("layout-rs = {{version = \"{}\"", LAYOUT_RS_VERSION);
+        assert!(manifest.contains(&requirement), "update LAYOUT_RS_VERSION");
+        assert!(BuiltinGraphviz.cache_key(&bad).contains(LAYOUT_RS_VERSION));
+    }
+
+    #[test]
+    fn asset_files() {
+        if !cfg!(unix) {
+            return;
+        }
+        let output_dir = tempfile::tempdir().unwrap();
+        let mut registry = Registry::default();
+        registry.register("cat", Box::new(CommandRenderer::new("cat", Vec::new())));
+        registry.set_format(DiagramFormat::Svg, output_dir.path());
+        let diagram = Diagram {
+            language: "cat".to_owned(),
+            attributes: vec!["process".to_owned(), "alt=A \"cat\"".to_owned()],
+            source: "<svg></svg>\n".to_owned(),
+        };
+        let html = registry
+            .to_html(&diagram, &output_dir.path().join("part/chapter.html"))
+            .unwrap();
+        let filename = format!("{:016x}.svg", fnv1a(b"<svg></svg>\n"));
+        assert_eq!(
+            html,
+            format!(
+                "<figure>\n<img src=\"../assets/{}\" alt=\"A &quot;cat&quot;\">\n</figure>\n",
+                filename
+            )
+        );
+        let written = std::fs::read(output_dir.path().join("assets").join(filename)).unwrap();
+        assert_eq!(written, b"<svg></svg>\n");
+    }
+}
@@ -0,0 +1,84 @@
+//! Machine-readable description of a book and its slide decks
+
+use std::path::{Path, PathBuf};
+
+use crate::{diagram, slides, Book, Error, IndexEntry};
+
+/// The version of the format produced by [`dump`].
+///
+/// Bump this whenever a field is removed or changes meaning. Adding fields
+/// does not require a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(serde::Serialize)]
+struct Dump<'a> {
+    schema_version: u32,
+    mdslides_version: &'static str,
+    title: &'a str,
+    index: &'a [IndexEntry],
+    chapters: Vec<ChapterDump<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct ChapterDump<'a> {
+    title: &'a str,
+    path: &'a str,
+    source_path: PathBuf,
+    front_matter: &'a toml::Table,
+    slide_count: usize,
+    slides: Vec<SlideDump<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct SlideDump<'a> {
+    title: Option<&'a str>,
+    notes: Option<&'a str>,
+    diagrams: Vec<&'a slides::Diagram>,
+}
+
+/// Write a JSON description of an mdbook and the slide decks it would produce.
+///
+/// The output contains the book's index (as per [`load_book`](crate::load_book)),
+/// and for every chapter, its location on disk and a list of its slides, with
+/// their titles, diagrams and speaker notes. No HTML is generated.
+///
+/// * `mdbook_path` - the location of the mdbook's `book.toml`. Assumed to be
+///   the current directory if not given.
+/// * `output` - where to write the JSON
+pub fn dump(mdbook_path: Option<&Path>, output: &mut dyn std::io::Write) -> Result<(), Error> {
+    let book = Book::load(mdbook_path)?;
+    let diagrams = diagram::Registry::from_config(&book.config)?;
+    let loaded = book.load_chapters(&diagrams.languages())?;
+
+    let chapters = loaded
+        .iter()
+        .map(|chapter| ChapterDump {
+            title: chapter.title,
+            path: chapter.path,
+            source_path: chapter.source_path.clone(),
+            front_matter: &chapter.front_matter,
+            slide_count: chapter.slides.len(),
+            slides: chapter
+                .slides
+                .iter()
+                .map(|slide| SlideDump {
+                    title: slide.title.as_deref(),
+                    notes: slide.notes.as_deref(),
+                    diagrams: slide.diagrams().collect(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let dump = Dump {
+        schema_version: SCHEMA_VERSION,
+        mdslides_version: env!("CARGO_PKG_VERSION"),
+        title: &book.title,
+        index: &book.index_entries,
+        chapters,
+    };
+    serde_json::to_writer_pretty(&mut *output, &dump)?;
+    writeln!(output)?;
+
+    Ok(())
+}
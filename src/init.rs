@@ -0,0 +1,111 @@
+//! Creating a new book of slides, or adding mdslides to an existing mdbook
+
+use std::path::{Path, PathBuf};
+
+use crate::{template, Error};
+
+/// The name of the example chapter we create
+const EXAMPLE_CHAPTER: &str = "example-slides.md";
+
+/// The `mdslides.toml` file we create
+const MDSLIDES_TOML: &str = r#"[slides]
+output_dir = "slides"
+template = "templates/slides.html"
+index_template = "templates/index.html"
+theme = "black"
+"#;
+
+/// Set up an mdbook for use with mdslides.
+///
+/// Creates `book.toml` and `SUMMARY.md` if the book doesn't have them, and
+/// adds an `mdslides.toml` file, slide and index templates (copies of the
+/// built-in ones), and an example chapter, which is added to `SUMMARY.md`.
+///
+/// * `mdbook_path` - the folder for the mdbook, which is created if required.
+///   Assumed to be the current directory if not given.
+/// * `force` - overwrite any of our files which already exist, rather than
+///   returning [`Error::FileExists`]
+pub fn init(mdbook_path: Option<&Path>, force: bool) -> Result<(), Error> {
+    let mdbook_path = mdbook_path.unwrap_or_else(|| Path::new("."));
+    std::fs::create_dir_all(mdbook_path)?;
+
+    let book_toml_path = mdbook_path.join("book.toml");
+    let book_src = if book_toml_path.exists() {
+        let book_config: toml::Table = toml::from_str(&std::fs::read_to_string(&book_toml_path)?)?;
+        book_config
+            .get("book")
+            .and_then(|t| t.get("src"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("src")
+            .to_owned()
+    } else {
+        "src".to_owned()
+    };
+    let src_dir = mdbook_path.join(&book_src);
+
+    let files: [(PathBuf, &str); 4] = [
+        (mdbook_path.join("mdslides.toml"), MDSLIDES_TOML),
+        (
+            mdbook_path.join("templates").join("slides.html"),
+            template::REVEAL_TEMPLATE,
+        ),
+        (
+            mdbook_path.join("templates").join("index.html"),
+            template::INDEX_TEMPLATE,
+        ),
+        (
+            src_dir.join(EXAMPLE_CHAPTER),
+            include_str!("templates/example.md"),
+        ),
+    ];
+
+    // Check everything before we write anything
+    if !force {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Err(Error::FileExists(path.clone()));
+        }
+    }
+
+    if !book_toml_path.exists() {
+        let title = std::fs::canonicalize(mdbook_path)?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Slides".to_owned());
+        let mut book_table = toml::Table::new();
+        book_table.insert("authors".to_owned(), toml::Value::Array(Vec::new()));
+        book_table.insert("language".to_owned(), "en".into());
+        book_table.insert("src".to_owned(), book_src.clone().into());
+        book_table.insert("title".to_owned(), title.into());
+        let mut book_config = toml::Table::new();
+        book_config.insert("book".to_owned(), book_table.into());
+        log::info!("Creating {}", book_toml_path.display());
+        std::fs::write(&book_toml_path, book_config.to_string())?;
+    }
+
+    for (path, contents) in files.iter() {
+        log::info!("Creating {}", path.display());
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, contents)?;
+    }
+
+    let summary_path = src_dir.join("SUMMARY.md");
+    let example_link = format!("- [Example Slides](./{})\n", EXAMPLE_CHAPTER);
+    if !summary_path.exists() {
+        log::info!("Creating {}", summary_path.display());
+        std::fs::write(&summary_path, format!("# Summary\n\n{}", example_link))?;
+    } else {
+        let mut summary = std::fs::read_to_string(&summary_path)?;
+        if !summary.contains(EXAMPLE_CHAPTER) {
+            log::info!("Adding example chapter to {}", summary_path.display());
+            if !summary.is_empty() && !summary.ends_with('\n') {
+                summary.push('\n');
+            }
+            summary.push_str(&example_link);
+            std::fs::write(&summary_path, summary)?;
+        }
+    }
+
+    Ok(())
+}
@@ -2,8 +2,34 @@
 //!
 //! Written by Jonathan Pallant at Ferrous Systems
 
-use std::io::prelude::*;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+mod agenda;
+mod assets;
+mod backend;
+pub mod cache;
+mod check;
+pub mod config;
+pub mod diagram;
+mod dump;
+mod init;
+pub mod links;
+mod lint;
+pub mod search;
+pub mod slides;
+mod template;
+pub mod timing;
+
+pub use backend::Backend;
+pub use cache::clean_cache;
+pub use check::{check, CheckOptions, Problem};
+pub use config::Config;
+pub use diagram::DiagramFormat;
+pub use dump::dump;
+pub use init::init;
+pub use lint::{lint, lint_slides, Lint, Rule, Severity};
+pub use template::{Template, TemplateSyntax};
 
 /// Describes the ways in which this library can fail.
 #[derive(thiserror::Error, Debug)]
@@ -26,106 +52,178 @@ pub enum Error {
     NoMdslidesConfigFound,
     #[error("Invalid toml input file")]
     FormatError(#[from] toml::de::Error),
+    #[error("JSON Error {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Template Error {0}")]
+    Template(#[from] minijinja::Error),
+    #[error("Invalid glob pattern {0}")]
+    Glob(#[from] glob::PatternError),
+    #[error("No output directory given, with --output-dir or in mdslides.toml")]
+    NoOutputDir,
+    #[error("{0} already exists (use --force to overwrite it)")]
+    FileExists(PathBuf),
+    #[error("Can't render {0} diagrams: {1}. Or set `missing_renderer = \"fallback\"` in the `[slides]` table of mdslides.toml to show their source instead.")]
+    RendererNotAvailable(String, String),
+    #[error("Found {0} problem(s) with the book")]
+    CheckFailed(usize),
+    #[error("Found {0} lint error(s)")]
+    LintFailed(usize),
+    #[error("Bad agenda in mdslides.toml: {0}")]
+    BadAgenda(String),
+    #[error("Unknown diagram renderer {0:?}")]
+    UnknownRenderer(String),
+    #[error("Diagrams in {0:?} need either a renderer or a command, but not both")]
+    BadDiagramConfig(String),
+    #[error("Failed to render the {1:?} diagram on slide {2} of {0}: {3}")]
+    DiagramFailed(String, String, usize, std::io::Error),
+    #[error("Can't use `reveal_js = \"bundled\"`, as mdslides was built without the bundled-reveal-js feature")]
+    NoBundledRevealJs,
 }
 
 /// Represents an entry in the index page
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum IndexEntry {
     /// A `#` or `<h1>` heading
     Heading(String),
     /// A `##` or `<h2>` heading
     SubHeading(String),
-    /// A chapter, with a link title and a file path
-    Chapter { title: String, path: String },
+    /// A chapter, with a link title, a file path and a section number
+    Chapter {
+        title: String,
+        path: String,
+        number: SectionNumber,
+    },
 }
 
-/// Generate a slide deck from an mdbook.
-///
-/// Generating slides requires a `mdslides.toml` file in the folder which also contains the
-/// `book.toml` file.
+/// What the index shows about a chapter's slide deck
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct DeckSummary {
+    /// How many slides are in the deck
+    pub slide_count: usize,
+    /// The estimated time to present the deck, in seconds
+    pub time: u32,
+    /// The `description` from the chapter's front matter, if any
+    pub description: Option<String>,
+}
+
+/// The number of a chapter in the book, like `1.2.`
 ///
-/// * `mdbook_path` - the location of the mdbook's `book.toml`. Assumed to be
-///   the current directory if not given.
-/// * `output_dir` - where to write the HTML slides
-/// * `slide_template` - a template for the slide decks, containing `$VARIABLES` for
-///   substitution
-/// * `index_template` - a template for the index file, containing `$VARIABLES`
-///   for substitution
-pub fn run(
-    mdbook_path: Option<&Path>,
-    output_dir: &Path,
-    slide_template: &str,
-    index_template: Option<&str>,
-) -> Result<(), Error> {
-    let mdbook_path = mdbook_path.unwrap_or_else(|| Path::new("."));
+/// Chapters are numbered in the same way as mdbook numbers them, with one
+/// number for each level of nesting.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(transparent)]
+pub struct SectionNumber(pub Vec<u32>);
 
-    let mdbook_toml_path = {
-        let mut path = mdbook_path.to_owned();
-        path.push("book.toml");
-        path
-    };
-    if !mdbook_toml_path.exists() {
-        return Err(Error::NoBookConfigFound);
+impl std::fmt::Display for SectionNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for number in self.0.iter() {
+            write!(f, "{}.", number)?;
+        }
+        Ok(())
     }
+}
 
-    let mdslides_toml_path = {
-        let mut path = mdbook_path.to_owned();
-        path.push("mdslides.toml");
-        path
-    };
-    if !mdslides_toml_path.exists() {
-        return Err(Error::NoMdslidesConfigFound);
-    }
-
-    log::info!("Loading book: {}", mdbook_toml_path.display());
-    let book_config_src = std::fs::read_to_string(&mdbook_toml_path)?;
-    let book_config: toml::Table = toml::from_str(&book_config_src)?;
-    let mdslides_config_src = std::fs::read_to_string(&mdslides_toml_path)?;
-    let mdslides_config: toml::Table = toml::from_str(&mdslides_config_src)?;
-    let skip_list_config = mdslides_config.get("slides").and_then(|t| t.as_table());
-    let book_config = book_config
-        .get("book")
-        .and_then(|t| t.as_table())
-        .ok_or(Error::NoBookTable)?;
-    log::debug!("Book config:\n{:?}", book_config);
-
-    let book_title = book_config
-        .get("title")
-        .and_then(|v| v.as_str())
-        .ok_or(Error::NoTitleField)?;
-    let book_src = book_config
-        .get("src")
-        .and_then(|v| v.as_str())
-        .ok_or(Error::NoSrcField)?;
-    log::info!("Book title: {:?}", book_title);
-    log::info!("Book src: {:?}", book_src);
-    log::info!("Book skip list: {:?}", skip_list_config);
-
-    let mdbook_summary_path = {
-        let mut path = mdbook_path.join(book_src);
-        path.push("SUMMARY.md");
-        path
-    };
+/// An mdbook, as loaded by mdslides.
+#[derive(Debug, Clone)]
+pub struct Book {
+    /// The folder containing the `book.toml` file
+    pub root: PathBuf,
+    /// The title of the book
+    pub title: String,
+    /// The authors of the book
+    pub authors: Vec<String>,
+    /// The language the book is written in, if given
+    pub language: Option<String>,
+    /// The folder containing `SUMMARY.md` and the chapters
+    pub src_dir: PathBuf,
+    /// The contents of `SUMMARY.md`, less any skipped chapters
+    pub index_entries: Vec<IndexEntry>,
+    /// The contents of `mdslides.toml`
+    pub config: Config,
+}
+
+impl Book {
+    /// Load an mdbook, and its `mdslides.toml` file.
+    ///
+    /// * `mdbook_path` - the location of the mdbook's `book.toml`. Assumed to be
+    ///   the current directory if not given.
+    pub fn load(mdbook_path: Option<&Path>) -> Result<Book, Error> {
+        let mdbook_path = mdbook_path.unwrap_or_else(|| Path::new("."));
+
+        let mdbook_toml_path = {
+            let mut path = mdbook_path.to_owned();
+            path.push("book.toml");
+            path
+        };
+        if !mdbook_toml_path.exists() {
+            return Err(Error::NoBookConfigFound);
+        }
+
+        let mdslides_toml_path = {
+            let mut path = mdbook_path.to_owned();
+            path.push("mdslides.toml");
+            path
+        };
+        if !mdslides_toml_path.exists() {
+            return Err(Error::NoMdslidesConfigFound);
+        }
+
+        log::info!("Loading book: {}", mdbook_toml_path.display());
+        let book_config_src = std::fs::read_to_string(&mdbook_toml_path)?;
+        let book_config: toml::Table = toml::from_str(&book_config_src)?;
+        let config = Config::load(&mdslides_toml_path)?;
+        let book_config = book_config
+            .get("book")
+            .and_then(|t| t.as_table())
+            .ok_or(Error::NoBookTable)?;
+        log::debug!("Book config:\n{:?}", book_config);
 
-    log::info!("Loading book summary: {}", mdbook_summary_path.display());
-    let mut summary_src = std::fs::read_to_string(&mdbook_summary_path)?;
+        let book_title = book_config
+            .get("title")
+            .and_then(|v| v.as_str())
+            .ok_or(Error::NoTitleField)?;
+        let book_src = book_config
+            .get("src")
+            .and_then(|v| v.as_str())
+            .ok_or(Error::NoSrcField)?;
+        let book_authors = book_config
+            .get("authors")
+            .and_then(|v| v.as_array())
+            .map(|authors| {
+                authors
+                    .iter()
+                    .filter_map(|author| author.as_str())
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let book_language = book_config
+            .get("language")
+            .and_then(|v| v.as_str())
+            .map(str::to_owned);
+        log::info!("Book title: {:?}", book_title);
+        log::info!("Book src: {:?}", book_src);
+        log::info!("Book skip list: {:?}", config.slides.skip);
 
-    // Filter `skip`ped slides:
-    // If it is the case that the `mdslides.toml` contains an `skip` entry
-    if let Some(skip_list) = skip_list_config {
-        // And that entry has a `skip = ["..."]` array defined,
-        if let Some(skip_list) = skip_list.get("skip") {
+        let mdbook_summary_path = {
+            let mut path = mdbook_path.join(book_src);
+            path.push("SUMMARY.md");
+            path
+        };
+
+        log::info!("Loading book summary: {}", mdbook_summary_path.display());
+        let mut summary_src = std::fs::read_to_string(&mdbook_summary_path)?;
+
+        // Filter `skip`ped slides:
+        // If it is the case that the `mdslides.toml` contains a `skip = ["..."]` array,
+        if !config.slides.skip.is_empty() {
             // Then we filter out the files in `skip`
             summary_src = summary_src
                 .lines()
                 .filter(|haystack| {
-                    // We can unwrap because we know `skip_list` is a toml array
-                    let skip_list = skip_list.as_array().unwrap();
-                    skip_list.iter().all(|needle| {
-                        // toml string arrays give you the opening and closing quotes - we need to trim them
-                        let needle = &needle.to_string();
-                        let needle = needle.trim_matches('"');
-                        if haystack.contains(&needle.to_string()) {
+                    config.slides.skip.iter().all(|needle| {
+                        if haystack.contains(needle.as_str()) {
                             log::info!("Skip: {haystack} {needle}");
                             // Don't want this line
                             false
@@ -138,42 +236,530 @@ pub fn run(
                 // .lines() iterator chopped off the newlines, we have to put them back in
                 .map(|s| s.to_string() + "\n")
                 .collect::<String>()
+        };
+        let index_entries = load_book(&summary_src)?;
+
+        Ok(Book {
+            root: mdbook_path.to_owned(),
+            title: book_title.to_owned(),
+            authors: book_authors,
+            language: book_language,
+            src_dir: mdbook_path.join(book_src),
+            index_entries,
+            config,
+        })
+    }
+
+    /// Iterate through the chapters which have a file to process, as `(title, path)`.
+    pub fn chapters(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.index_entries.iter().filter_map(|entry| match entry {
+            IndexEntry::Chapter { title, path, .. } if !path.is_empty() => {
+                Some((title.as_str(), path.as_str()))
+            }
+            _ => None,
+        })
+    }
+
+    /// Get the location on disk of a chapter, given its path in `SUMMARY.md`.
+    pub fn chapter_path(&self, path: &str) -> PathBuf {
+        self.src_dir.join(chapter_file(path))
+    }
+
+    /// Read every chapter which has a file, and split it into slides.
+    pub fn load_chapters(&self, diagram_languages: &[&str]) -> Result<Vec<Chapter<'_>>, Error> {
+        self.chapters()
+            .map(|(title, path)| {
+                Chapter::load(title, path, self.chapter_path(path), diagram_languages)
+            })
+            .collect()
+    }
+}
+
+/// A chapter of a book, read from disk and split into slides
+#[derive(Debug, Clone)]
+pub struct Chapter<'a> {
+    /// The title of the chapter
+    pub title: &'a str,
+    /// The chapter's path in `SUMMARY.md`
+    pub path: &'a str,
+    /// The location of the chapter on disk
+    pub source_path: PathBuf,
+    /// The contents of the chapter's file
+    pub content: String,
+    /// The chapter's front matter, which is empty if it has none
+    pub front_matter: toml::Table,
+    /// The chapter's slides, not including the front matter
+    pub slides: Vec<slides::Slide>,
+}
+
+impl<'a> Chapter<'a> {
+    /// Read a chapter from `source_path`, and split it into slides.
+    ///
+    /// Code blocks in the given `diagram_languages` become diagrams.
+    pub fn load(
+        title: &'a str,
+        path: &'a str,
+        source_path: PathBuf,
+        diagram_languages: &[&str],
+    ) -> Result<Chapter<'a>, Error> {
+        log::info!("Loading {}", source_path.display());
+        let content = std::fs::read_to_string(&source_path)?;
+        let (front_matter, markdown) = slides::front_matter(&content)?;
+        let slides = slides::split(markdown, diagram_languages);
+        Ok(Chapter {
+            title,
+            path,
+            source_path,
+            front_matter,
+            slides,
+            content,
+        })
+    }
+}
+
+/// Get the location of a chapter's file relative to the book's `src` folder,
+/// given the chapter's path in `SUMMARY.md`, which may be percent-encoded.
+pub fn chapter_file(path: &str) -> PathBuf {
+    PathBuf::from(percent_decode(path))
+}
+
+/// Get the URL of a chapter's deck, relative to the output directory, given
+/// the chapter's path in `SUMMARY.md`.
+///
+/// Only a `.md` extension is changed, so `./cmd-tools.md` becomes
+/// `./cmd-tools.html`. Anything else, like percent-encoding, is kept.
+pub fn deck_url(path: &str, extension: &str) -> String {
+    let stem = path.strip_suffix(".md").unwrap_or(path);
+    format!("{}.{}", stem, extension)
+}
+
+/// Get the location on disk of a chapter's deck, given the chapter's path
+/// in `SUMMARY.md`.
+pub fn deck_path(output_dir: &Path, path: &str, extension: &str) -> PathBuf {
+    let url = deck_url(path, extension);
+    output_dir.join(percent_decode(url.trim_start_matches("./")))
+}
+
+/// How many folders below the output directory a page is, given its path
+/// relative to the output directory.
+pub(crate) fn page_depth(path: &Path) -> usize {
+    assets::normalise(path)
+        .and_then(|path| path.parent().map(|parent| parent.components().count()))
+        .unwrap_or(0)
+}
+
+/// Change a URL which is relative to the output directory so that it works
+/// from a page `depth` folders below the output directory.
+///
+/// Absolute URLs, like `https://...` or `/theme.css`, are left alone.
+pub(crate) fn relative_url(url: &str, depth: usize) -> String {
+    let is_relative = !url.contains("://")
+        && !url.starts_with('/')
+        && !url.starts_with('#')
+        && !url.starts_with("data:");
+    if depth == 0 || !is_relative {
+        return url.to_owned();
+    }
+    format!("{}{}", "../".repeat(depth), url.trim_start_matches("./"))
+}
+
+/// Decode any `%XX` escapes in a URL path.
+pub(crate) fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let escaped = bytes
+            .get(idx + 1..idx + 3)
+            .filter(|_| bytes[idx] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            None => {
+                decoded.push(bytes[idx]);
+                idx += 1;
+            }
         }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The themes which come with reveal.js
+pub const THEMES: &[&str] = &[
+    "beige",
+    "black",
+    "blood",
+    "dracula",
+    "league",
+    "moon",
+    "night",
+    "serif",
+    "simple",
+    "sky",
+    "solarized",
+    "white",
+];
+
+/// The reveal.js theme used if none is given
+const DEFAULT_THEME: &str = "black";
+
+/// Where to find reveal.js, if a local copy is not given
+const DEFAULT_REVEAL_JS: &str = "https://cdn.jsdelivr.net/npm/reveal.js@5.1.0";
+
+/// The `reveal_js` setting which uses the copy of reveal.js built into mdslides
+const BUNDLED_REVEAL_JS: &str = "bundled";
+
+/// Options for [`build`], usually given on the command line.
+///
+/// Anything left as `None` is taken from `mdslides.toml`, or given a default.
+/// Paths are relative to the current directory, whereas paths in
+/// `mdslides.toml` are relative to the book's root folder.
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Where to write the slides
+    pub output_dir: Option<PathBuf>,
+    /// The template for the slide decks
+    pub template: Option<PathBuf>,
+    /// The template for the index file
+    pub index_template: Option<PathBuf>,
+    /// The slide framework to generate decks for
+    pub backend: Option<Backend>,
+    /// The syntax of the templates
+    pub template_syntax: Option<TemplateSyntax>,
+    /// The reveal.js theme
+    pub theme: Option<String>,
+    /// How to put rendered diagrams into the slides
+    pub diagram_format: Option<DiagramFormat>,
+}
+
+/// Generate slide decks from an mdbook, using the settings in its `mdslides.toml`.
+///
+/// * `mdbook_path` - the location of the mdbook's `book.toml`. Assumed to be
+///   the current directory if not given.
+/// * `options` - settings which override those in `mdslides.toml`
+pub fn build(mdbook_path: Option<&Path>, options: &BuildOptions) -> Result<(), Error> {
+    let mut book = Book::load(mdbook_path)?;
+
+    // Command line options take priority over `mdslides.toml`
+    let slides_config = &mut book.config.slides;
+    let from_config = |path: &Option<PathBuf>| path.as_ref().map(|path| book.root.join(path));
+    let output_dir = options
+        .output_dir
+        .clone()
+        .or_else(|| from_config(&slides_config.output_dir))
+        .ok_or(Error::NoOutputDir)?;
+    let template_path = options
+        .template
+        .clone()
+        .or_else(|| from_config(&slides_config.template));
+    let index_template_path = options
+        .index_template
+        .clone()
+        .or_else(|| from_config(&slides_config.index_template));
+    let backend = options
+        .backend
+        .or(slides_config.backend)
+        .unwrap_or_default();
+    let template_syntax = options
+        .template_syntax
+        .or(slides_config.template_syntax)
+        .unwrap_or_default();
+    slides_config.template_syntax = Some(template_syntax);
+    if options.theme.is_some() {
+        slides_config.theme.clone_from(&options.theme);
+    }
+    if options.diagram_format.is_some() {
+        slides_config.diagram_format = options.diagram_format;
+    }
+
+    let slide_template = if let Some(template_path) = template_path {
+        log::info!("Loading slide template: {}", template_path.display());
+        Template::load(&template_path, template_syntax)?
+    } else {
+        log::info!("Using built-in slide template");
+        // Backends without a built-in template don't use a template at all
+        Template::builtin_slides(backend).unwrap_or_else(|| Template::new("", template_syntax))
     };
-    let index_entries = load_book(&summary_src)?;
 
+    let index_template = if let Some(index_template_path) = index_template_path {
+        log::info!("Using index template: {}", index_template_path.display());
+        Template::load(&index_template_path, template_syntax)?
+    } else {
+        log::info!("Using built-in index template");
+        Template::builtin_index()
+    };
+
+    generate_book(
+        &book,
+        &output_dir,
+        &slide_template,
+        Some(&index_template),
+        backend,
+    )
+}
+
+/// Generate a slide deck from an mdbook.
+///
+/// Generating slides requires a `mdslides.toml` file in the folder which also contains the
+/// `book.toml` file. The templates, backend and output directory given here
+/// are used instead of the ones in that file - see [`build`] to use those too.
+///
+/// * `mdbook_path` - the location of the mdbook's `book.toml`. Assumed to be
+///   the current directory if not given.
+/// * `output_dir` - where to write the HTML slides
+/// * `slide_template` - a template for the slide decks
+/// * `index_template` - a template for the index file
+/// * `backend` - the slide framework to generate decks for
+pub fn run(
+    mdbook_path: Option<&Path>,
+    output_dir: &Path,
+    slide_template: &Template,
+    index_template: Option<&Template>,
+    backend: Backend,
+) -> Result<(), Error> {
+    let book = Book::load(mdbook_path)?;
+    generate_book(&book, output_dir, slide_template, index_template, backend)
+}
+
+/// Find the languages of all the diagrams in some chapters.
+fn diagram_languages_used(chapters: &[Chapter]) -> BTreeSet<String> {
+    chapters
+        .iter()
+        .flat_map(|chapter| chapter.slides.iter())
+        .flat_map(|slide| slide.diagrams())
+        .map(|diagram| diagram.language.clone())
+        .collect()
+}
+
+/// Summarise each chapter's deck, by the chapter's path in `SUMMARY.md`,
+/// and log the estimated time to present each one.
+fn deck_summaries<'a>(
+    chapters: &[Chapter<'a>],
+    timing: &config::TimingConfig,
+) -> BTreeMap<&'a str, DeckSummary> {
+    let mut decks = BTreeMap::new();
+    for chapter in chapters {
+        let time = timing::deck_time(&chapter.slides, timing);
+        log::info!(
+            "{}: {} slides, about {}",
+            chapter.path,
+            chapter.slides.len(),
+            timing::format_duration(time)
+        );
+        let description = chapter
+            .front_matter
+            .get("description")
+            .and_then(|value| value.as_str())
+            .map(str::to_owned);
+        decks.insert(
+            chapter.path,
+            DeckSummary {
+                slide_count: chapter.slides.len(),
+                time,
+                description,
+            },
+        );
+    }
+    log::info!(
+        "Whole book: about {}",
+        timing::format_duration(decks.values().map(|deck| deck.time).sum())
+    );
+    decks
+}
+
+/// Generate the slide decks, and optionally the index, for a loaded book.
+fn generate_book(
+    book: &Book,
+    output_dir: &Path,
+    slide_template: &Template,
+    index_template: Option<&Template>,
+    backend: Backend,
+) -> Result<(), Error> {
     std::fs::create_dir_all(output_dir)?;
 
+    let reveal_js = match book.config.slides.reveal_js.as_deref() {
+        None => DEFAULT_REVEAL_JS.to_owned(),
+        Some(url) if url.contains("://") => url.to_owned(),
+        Some(BUNDLED_REVEAL_JS) => {
+            log::info!("Writing the bundled copy of reveal.js");
+            assets::write_bundled_reveal_js(&output_dir.join("reveal.js"))?;
+            "./reveal.js".to_owned()
+        }
+        Some(path) => {
+            let from = book.root.join(path);
+            log::info!("Copying reveal.js from {}", from.display());
+            copy_dir(&from, &output_dir.join("reveal.js"))?;
+            "./reveal.js".to_owned()
+        }
+    };
+    let theme = book.config.slides.theme.as_deref().unwrap_or(DEFAULT_THEME);
+    let theme_url = if THEMES.contains(&theme) {
+        format!("{}/dist/theme/{}.css", reveal_js, theme)
+    } else {
+        theme.to_owned()
+    };
+
+    let mut diagrams = diagram::Registry::from_config(&book.config)?;
+    diagrams.set_format(
+        book.config.slides.diagram_format.unwrap_or_default(),
+        output_dir,
+    );
+    diagrams.set_cache(cache::Cache::for_book(book));
+    let chapters = book.load_chapters(&diagrams.languages())?;
+    let links = links::Links::for_chapters(&book.src_dir, &chapters);
+    diagrams.check_available(
+        diagram_languages_used(&chapters).iter().map(String::as_str),
+        book.config.slides.missing_renderer.unwrap_or_default(),
+    )?;
+
+    // Variables available to every template. URLs are relative to the output
+    // directory, so pages in sub-folders get them with `../` in front.
+    let decks = deck_summaries(&chapters, &book.config.timing);
+    let total_time: u32 = decks.values().map(|deck| deck.time).sum();
+    let date = build_date();
+    let chapter_links = |depth: usize| -> Vec<minijinja::Value> {
+        book.chapters()
+            .map(|(title, path)| {
+                let time = decks.get(path).map(|deck| deck.time).unwrap_or_default();
+                minijinja::context! {
+                    title,
+                    url => relative_url(&deck_url(path, backend.extension()), depth),
+                    time,
+                    duration => timing::format_duration(time),
+                }
+            })
+            .collect()
+    };
+    let book_context = |chapters: &[minijinja::Value], depth: usize| -> minijinja::Value {
+        let agendas: Vec<minijinja::Value> = book
+            .config
+            .agendas
+            .iter()
+            .map(|agenda| {
+                minijinja::context! {
+                    title => &agenda.title,
+                    url => relative_url(&format!("./{}", agenda.file.display()), depth),
+                }
+            })
+            .collect();
+        minijinja::context! {
+            book => minijinja::context! {
+                title => &book.title,
+                authors => &book.authors,
+                language => &book.language,
+            },
+            chapters,
+            agendas,
+            search => book.config.slides.search,
+            reveal_js => relative_url(&reveal_js, depth),
+            theme_url => relative_url(&theme_url, depth),
+            total_time,
+            total_duration => timing::format_duration(total_time),
+            date => &date,
+            mdslides_version => env!("CARGO_PKG_VERSION"),
+        }
+    };
+
+    // Load any per-chapter templates
+    let mut template_overrides = Vec::new();
+    for template_override in book.config.slides.templates.iter() {
+        let path = book.root.join(&template_override.template);
+        log::info!("Loading slide template: {}", path.display());
+        let template = Template::load(
+            &path,
+            book.config.slides.template_syntax.unwrap_or_default(),
+        )?;
+        template_overrides.push((template_override, template));
+    }
+
     // Process each chapter
-    for entry in index_entries.iter() {
+    let mut search_index = search::SearchIndex::default();
+    let mut part = None;
+    let mut subheading = None;
+    let mut chapter_idx: usize = 0;
+    for entry in book.index_entries.iter() {
         match entry {
-            IndexEntry::Heading(_heading) => {
-                // Ignore
+            IndexEntry::Heading(heading) => {
+                part = Some(heading);
+                subheading = None;
             }
-            IndexEntry::SubHeading(_heading) => {
-                // Ignore
+            IndexEntry::SubHeading(heading) => {
+                subheading = Some(heading);
             }
-            IndexEntry::Chapter { title, path } if path.is_empty() => {
+            IndexEntry::Chapter { title, path, .. } if path.is_empty() => {
                 log::info!("Processing placeholder: {:?}", title);
             }
-            IndexEntry::Chapter { title, path } => {
+            IndexEntry::Chapter {
+                title,
+                path,
+                number,
+            } => {
                 log::info!("Processing {}: {:?}", path, title);
-                let in_path = {
-                    let mut temp_path = mdbook_path.join(book_src);
-                    temp_path.push(path);
-                    temp_path
-                };
-                let out_path = {
-                    let mut temp_path = output_dir.to_owned();
-                    let new_filename = path.replace("md", "html");
-                    temp_path.push(new_filename);
-                    temp_path
+                // `chapters` are in the same order as the chapters in the index
+                let chapter = &chapters[chapter_idx];
+                let out_path = deck_path(output_dir, path, backend.extension());
+                if let Some(parent) = out_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let mut template = slide_template;
+                if let Some((template_override, override_template)) =
+                    template_overrides.iter().find(|(template_override, _)| {
+                        template_override.matches(path, &chapter.front_matter)
+                    })
+                {
+                    log::info!("Using template {}", template_override.template.display());
+                    template = override_template;
+                }
+                let depth = page_depth(&chapter_file(path));
+                let links_here = chapter_links(depth);
+                let context = minijinja::context! {
+                    number => number.to_string(),
+                    part,
+                    subheading,
+                    prev => chapter_idx.checked_sub(1).and_then(|idx| links_here.get(idx)),
+                    next => links_here.get(chapter_idx + 1),
+                    ..book_context(&links_here, depth)
                 };
-                generate_deck(&in_path, &out_path, slide_template, title)?;
+                chapter_idx += 1;
+                generate_deck(
+                    chapter,
+                    &out_path,
+                    template,
+                    backend,
+                    &diagrams,
+                    &links,
+                    &book.config.timing,
+                    context,
+                )?;
+                if book.config.slides.search {
+                    search_index.add_deck(
+                        title,
+                        &deck_url(path, backend.extension()),
+                        &chapter.slides,
+                        backend,
+                    );
+                }
+                assets::copy_chapter_assets(book, path, &chapter.content, output_dir)?;
             }
         }
     }
 
+    assets::copy_static_dirs(book, output_dir)?;
+    if book.config.slides.search {
+        search_index.write(output_dir)?;
+    }
+
+    if let Some(cache) = diagrams.cache() {
+        let (hits, misses) = cache.stats();
+        if hits + misses > 0 {
+            log::info!("Diagram cache: {} hits, {} misses", hits, misses);
+        }
+    }
+
     // Generate index page
     if let Some(index_template) = index_template {
         log::info!("Processing index");
@@ -183,24 +769,89 @@ pub fn run(
             path
         };
         let mut output = std::fs::File::create(out_path)?;
-        generate_index(&index_entries, &mut output, index_template, book_title)?;
+        generate_index(
+            &book.index_entries,
+            &mut output,
+            index_template,
+            &book.title,
+            &decks,
+            backend.extension(),
+            book_context(&chapter_links(0), 0),
+        )?;
     }
 
+    agenda::generate_agendas(book, output_dir, &decks, backend.extension(), &|depth| {
+        book_context(&chapter_links(depth), depth)
+    })?;
+
     log::info!("Done!");
 
     Ok(())
 }
 
+/// Copy a folder, and everything in it.
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let to = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else {
+            std::fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the date of this build, as `YYYY-MM-DD`.
+///
+/// Uses `SOURCE_DATE_EPOCH` if it is set, to allow for reproducible builds.
+fn build_date() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default()
+        });
+    format_date(seconds)
+}
+
+/// Convert seconds since the UNIX epoch into a `YYYY-MM-DD` date.
+///
+/// This is the `civil_from_days` algorithm from
+/// <https://howardhinnant.github.io/date_algorithms.html>.
+fn format_date(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
 /// Load an mdbook summary file into a list of index entries.
 pub fn load_book(summary_src: &str) -> Result<Vec<IndexEntry>, Error> {
     let mut parser = pulldown_cmark::Parser::new(summary_src);
     let mut in_item = false;
     let mut index_entries = Vec::new();
     let mut last_link = None;
-    loop {
-        let Some(event) = parser.next() else {
-            break;
-        };
+    // How deeply nested in lists we are, and the item count at each level
+    let mut list_depth = 0;
+    let mut section_number = Vec::new();
+    while let Some(event) = parser.next() {
         log::trace!("Got event: {:?}", event);
         // Every link in the book looks like:
         // Got event: Start(Item)
@@ -235,8 +886,20 @@ pub fn load_book(summary_src: &str) -> Result<Vec<IndexEntry>, Error> {
                 };
                 index_entries.push(IndexEntry::SubHeading(content.to_string()));
             }
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::List(_)) => {
+                list_depth += 1;
+                section_number.resize(list_depth, 0);
+            }
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::List(_)) => {
+                list_depth -= 1;
+                // Numbering carries on between the top-level lists in each part
+                section_number.truncate(list_depth.max(1));
+            }
             pulldown_cmark::Event::Start(pulldown_cmark::Tag::Item) => {
                 in_item = true;
+                if let Some(count) = section_number.get_mut(list_depth.wrapping_sub(1)) {
+                    *count += 1;
+                }
             }
             pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link {
                 link_type: _,
@@ -244,17 +907,27 @@ pub fn load_book(summary_src: &str) -> Result<Vec<IndexEntry>, Error> {
                 title: _,
                 id: _,
             }) if in_item => {
-                last_link = Some(dest_url.to_string());
+                last_link = Some((dest_url.to_string(), String::new()));
             }
-            pulldown_cmark::Event::Text(title) if last_link.is_some() => {
-                let index_entry = IndexEntry::Chapter {
-                    title: title.to_string(),
-                    path: last_link.take().unwrap(),
-                };
-                index_entries.push(index_entry);
+            pulldown_cmark::Event::Text(text)
+            | pulldown_cmark::Event::Code(text)
+            | pulldown_cmark::Event::InlineHtml(text) => {
+                // A title may be split over several events, by entities,
+                // inline code or things that look like HTML tags
+                if let Some((_path, title)) = last_link.as_mut() {
+                    title.push_str(&text);
+                }
             }
             pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Link) if in_item => {
-                last_link = None;
+                if let Some((path, title)) = last_link.take() {
+                    if !title.is_empty() {
+                        index_entries.push(IndexEntry::Chapter {
+                            title,
+                            path,
+                            number: SectionNumber(section_number[..list_depth].to_vec()),
+                        });
+                    }
+                }
             }
             pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Item) => {
                 in_item = false;
@@ -267,18 +940,40 @@ pub fn load_book(summary_src: &str) -> Result<Vec<IndexEntry>, Error> {
     Ok(index_entries)
 }
 
-/// Processes a markdown file into an HTML document, using the given template.
+/// Processes a chapter into an HTML document, using the given template.
+///
+/// A legacy template should contain the string `$TITLE`, which is the title of
+/// the chapter (HTML escaped, or use `$RAW_TITLE` for the title as-is), and
+/// `$CONTENT` which will be the Markdown slide contents. We assume your
+/// template has an integrated Markdown-to-HTML convertor, like reveal.js does.
+///
+/// A Jinja template is given the variables `title`, `content`, `slides` (each
+/// with a `title`, `notes` and `classes`) and `front_matter`, along with
+/// anything in `context`. The `content` is not HTML escaped.
 ///
-/// The template should contain the string `$TITLE`, which is the title of the
-/// chapter, and `$CONTENT` which will be the Markdown slide contents. We assume
-/// your template has an integrated Markdown-to-HTML convertor, like reveal.js
-/// does.
+/// The slides are written in the Markdown dialect of the given `backend`. If
+/// the backend doesn't use a template (like Marp), the template is ignored and
+/// the Markdown is written out directly.
+///
+/// Links to other chapters are rewritten with `links`, to point at their
+/// decks.
+///
+/// Jinja templates are also given `time` and `duration`, the estimated time
+/// to present the deck in seconds and as text like `12m 30s`, and each slide
+/// has its own `time` and `duration`, estimated using the rates in `timing`.
+#[allow(clippy::too_many_arguments)]
 pub fn generate_deck(
-    in_path: &Path,
+    chapter: &Chapter,
     out_path: &Path,
-    template: &str,
-    title: &str,
-) -> std::io::Result<()> {
+    template: &Template,
+    backend: Backend,
+    diagrams: &diagram::Registry,
+    links: &links::Links,
+    timing: &config::TimingConfig,
+    context: minijinja::Value,
+) -> Result<(), Error> {
+    let in_path = &chapter.source_path;
+    let title = chapter.title;
     log::debug!(
         "in_path: {:?}, out_path: {:?}, title: {:?}",
         in_path,
@@ -286,132 +981,266 @@ pub fn generate_deck(
         title
     );
 
-    let generated = template.replace("$TITLE", title);
-
-    let content = std::fs::read_to_string(in_path)?;
-
-    let generated = generated.replace("$CONTENT", &content);
-
-    let mut output_file = std::fs::File::create(out_path)?;
-
-    let mut collecting_diagram: Option<String> = None;
-    let mut first = true;
-    for line in generated.lines() {
-        // Find end-of-block, in case it's the end of a diagram.
-        if line == "```" {
-            if let Some(diagram_str) = collecting_diagram.take() {
-                // This is the end of a dot block
-                log::debug!("Got graph: {:?}", diagram_str);
-                log::info!(
-                    "Calling graphviz to render diagram in {}",
-                    in_path.display()
-                );
-                let diagram = graphviz_rust::exec_dot(
-                    diagram_str,
-                    vec![graphviz_rust::cmd::CommandArg::Format(
-                        graphviz_rust::cmd::Format::Svg,
-                    )],
-                )
-                .expect("Failed to generate graph");
-                // insert the SVG in-line
-                writeln!(output_file, "<figure>")?;
-                output_file.write_all(&diagram)?;
-                writeln!(output_file, "</figure>")?;
-                // Don't emit the code fence
-                continue;
+    let slides = links.rewrite_slides(&chapter.slides, in_path, backend);
+    let markdown = backend.render(title, &slides, &mut |diagram| {
+        log::debug!("Got {} diagram: {:?}", diagram.language, diagram.source);
+        diagrams.to_html(diagram, out_path).map_err(|error| {
+            let slide = slides
+                .iter()
+                .position(|slide| slide.diagrams().any(|d| std::ptr::eq(d, diagram)))
+                .unwrap_or_default();
+            Error::DiagramFailed(
+                in_path.display().to_string(),
+                diagram.alt_text(),
+                slide + 1,
+                error,
+            )
+        })
+    })?;
+
+    if !backend.uses_template() {
+        std::fs::write(out_path, markdown)?;
+        return Ok(());
+    }
+
+    let slide_contexts: Vec<minijinja::Value> = slides
+        .iter()
+        .map(|slide| {
+            let time = timing::slide_time(slide, timing);
+            minijinja::context! {
+                title => slide.title,
+                notes => slide.notes,
+                classes => slide.classes,
+                time,
+                duration => timing::format_duration(time),
             }
-        }
+        })
+        .collect();
+    let time = timing::deck_time(&slides, timing);
+    let context = minijinja::context! {
+        title,
+        time,
+        duration => timing::format_duration(time),
+        content => minijinja::Value::from_safe_string(markdown.clone()),
+        slides => slide_contexts,
+        front_matter => &chapter.front_matter,
+        ..context
+    };
+    let generated = template.render(
+        &[
+            ("TITLE", &template::escape_html(title)),
+            ("RAW_TITLE", title),
+            ("CONTENT", &markdown),
+        ],
+        context,
+    )?;
 
-        // Are we in a diagram?
-        if let Some(graph_str) = collecting_diagram.as_mut() {
-            graph_str.push_str(line);
-            graph_str.push('\n');
-            // Don't emit the dot code
-            continue;
-        }
+    std::fs::write(out_path, generated)?;
 
-        // starting a new diagram
-        if line.starts_with("```dot") && line.contains("process") {
-            collecting_diagram = Some(String::new());
-            // Don't emit the code fence
-            continue;
-        }
+    Ok(())
+}
+
+/// An entry in the index, as given to index templates
+#[derive(Debug, serde::Serialize)]
+struct IndexNode<'a> {
+    /// `heading`, `subheading` or `chapter`
+    kind: &'static str,
+    title: &'a str,
+    /// How deeply nested a chapter is, starting at one
+    depth: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slide_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    /// The chapters nested inside this one
+    children: Vec<IndexNode<'a>>,
+}
 
-        // Fixup headings into slide breaks
-        if line.starts_with("## ") || line.starts_with("# ") {
-            // Don't put a --- before the first heading, as it's our first slide
-            if !first {
-                writeln!(output_file, "---")?;
-            } else {
-                first = false;
+impl<'a> IndexNode<'a> {
+    fn new(
+        entry: &'a IndexEntry,
+        decks: &'a BTreeMap<&str, DeckSummary>,
+        extension: &str,
+    ) -> IndexNode<'a> {
+        let (kind, title) = match entry {
+            IndexEntry::Heading(heading) => ("heading", heading),
+            IndexEntry::SubHeading(heading) => ("subheading", heading),
+            IndexEntry::Chapter { title, .. } => ("chapter", title),
+        };
+        let mut node = IndexNode {
+            kind,
+            title,
+            depth: 0,
+            number: None,
+            url: None,
+            slide_count: None,
+            time: None,
+            duration: None,
+            description: None,
+            children: Vec::new(),
+        };
+        if let IndexEntry::Chapter { path, number, .. } = entry {
+            node.depth = number.0.len().max(1);
+            node.number = Some(number.to_string());
+            if !path.is_empty() {
+                node.url = Some(deck_url(path, extension));
+            }
+            if let Some(deck) = decks.get(path.as_str()) {
+                node.slide_count = Some(deck.slide_count);
+                node.time = Some(deck.time);
+                node.duration = Some(timing::format_duration(deck.time));
+                node.description = deck.description.as_deref();
             }
         }
-        writeln!(output_file, "{}", line)?;
+        node
     }
 
-    Ok(())
+    /// Write this chapter as an HTML list item, with its sub-chapters in a
+    /// nested list.
+    fn write_html(&self, html: &mut String) {
+        let title = template::escape_html(self.title);
+        match self.url.as_deref() {
+            Some(url) => html.push_str(&format!(
+                "<li><a href=\"{}\">{}</a>",
+                template::escape_html(url),
+                title
+            )),
+            None => html.push_str(&format!("<li>{}", title)),
+        }
+        if let (Some(slide_count), Some(duration)) = (self.slide_count, self.duration.as_deref()) {
+            let plural = if slide_count == 1 { "" } else { "s" };
+            html.push_str(&format!(
+                " <small>({} slide{}, about {})</small>",
+                slide_count, plural, duration
+            ));
+        }
+        if let Some(description) = self.description {
+            html.push_str(&format!("\n<p>{}</p>", template::escape_html(description)));
+        }
+        if !self.children.is_empty() {
+            html.push('\n');
+            write_html_list(&self.children, html);
+        }
+        html.push_str("</li>\n");
+    }
+}
+
+/// Write some chapters as an HTML list.
+fn write_html_list(chapters: &[IndexNode], html: &mut String) {
+    html.push_str("<ul>\n");
+    for chapter in chapters {
+        chapter.write_html(html);
+    }
+    html.push_str("</ul>\n");
+}
+
+/// Arrange the entries in `SUMMARY.md` into a tree, with each chapter's
+/// sub-chapters as its `children`.
+fn index_tree<'a>(
+    entries: &'a [IndexEntry],
+    decks: &'a BTreeMap<&str, DeckSummary>,
+    extension: &str,
+) -> Vec<IndexNode<'a>> {
+    let mut tree: Vec<IndexNode> = Vec::new();
+    for entry in entries {
+        let node = IndexNode::new(entry, decks, extension);
+        // Find the list this chapter belongs in
+        let mut siblings = &mut tree;
+        let mut depth = 1;
+        while depth < node.depth
+            && siblings
+                .last()
+                .is_some_and(|last| last.kind == "chapter" && last.depth == depth)
+        {
+            let parent = siblings.last_mut().expect("checked above");
+            siblings = &mut parent.children;
+            depth += 1;
+        }
+        siblings.push(node);
+    }
+    tree
 }
 
 /// Processes a list of chapters into an HTML document, using the given template.
 ///
-/// The template should contain the string `$INDEX` which is replaced with
-/// a simple HTML unordered list of all the chapter headings as links.
+/// A legacy template should contain the string `$INDEX` which is replaced with
+/// HTML headings, and nested lists of links to the chapters, and may contain
+/// `$TITLE`, which is replaced with the title of the book (HTML escaped, or
+/// use `$RAW_TITLE` for the title as-is). Chapters found in `decks` are shown
+/// with their slide count, estimated time and description.
+///
+/// A Jinja template is given the variables `title`, `index` (the same HTML,
+/// not escaped), `entries` and `tree`, along with anything in `context`.
+/// `entries` are the entries of `SUMMARY.md` in order, each with a `kind` of
+/// `heading`, `subheading` or `chapter` and a `title`. Chapters also have a
+/// `depth`, a `number`, and a `url` if they have a file, along with a
+/// `slide_count`, `time` (in seconds), `duration` (like `12m 30s`) and
+/// `description` if they are in `decks`. `tree` has the same entries, but
+/// with sub-chapters in the `children` of their parent chapter.
+///
+/// Links to the decks use the file `extension` of the backend which
+/// generated them.
 pub fn generate_index(
     chapters: &[IndexEntry],
     output: &mut dyn std::io::Write,
-    template: &str,
+    template: &Template,
     title: &str,
-) -> std::io::Result<()> {
+    decks: &BTreeMap<&str, DeckSummary>,
+    extension: &str,
+    context: minijinja::Value,
+) -> Result<(), Error> {
+    let tree = index_tree(chapters, decks, extension);
+
     // Build chapter list as HTML
     let mut generated_html = String::new();
-    let mut heading_is_open = false;
-    for entry in chapters {
-        match entry {
-            IndexEntry::Chapter { title, path } if path.is_empty() => {
-                if !heading_is_open {
-                    generated_html.push_str("<ul>\n");
-                    heading_is_open = true;
-                }
-                generated_html.push_str(&format!("<li>{}</li>\n", title));
-            }
-            IndexEntry::Chapter { title, path } => {
-                if !heading_is_open {
-                    generated_html.push_str("<ul>\n");
-                    heading_is_open = true;
-                }
-                let new_filename = path.replace("md", "html");
-                generated_html.push_str(&format!(
-                    "<li><a href=\"{}\">{}</a></li>\n",
-                    new_filename, title
-                ));
-            }
-            IndexEntry::Heading(heading) => {
-                if heading_is_open {
-                    generated_html.push_str("</ul>\n");
-                    heading_is_open = false;
-                }
-                generated_html.push_str("<h1>");
-                generated_html.push_str(heading);
-                generated_html.push_str("</h1>\n");
-            }
-            IndexEntry::SubHeading(heading) => {
-                if heading_is_open {
-                    generated_html.push_str("</ul>\n");
-                    heading_is_open = false;
-                }
-                generated_html.push_str("<h2>");
-                generated_html.push_str(heading);
-                generated_html.push_str("</h2>\n");
-            }
+    let mut run_start = None;
+    for (idx, node) in tree.iter().enumerate() {
+        if node.kind == "chapter" {
+            run_start.get_or_insert(idx);
+            continue;
+        }
+        if let Some(start) = run_start.take() {
+            write_html_list(&tree[start..idx], &mut generated_html);
         }
+        let tag = if node.kind == "heading" { "h1" } else { "h2" };
+        generated_html.push_str(&format!(
+            "<{}>{}</{}>\n",
+            tag,
+            template::escape_html(node.title),
+            tag
+        ));
     }
-    if heading_is_open {
-        generated_html.push_str("</ul>\n");
+    if let Some(start) = run_start {
+        write_html_list(&tree[start..], &mut generated_html);
     }
 
-    let generated = template.replace("$INDEX", &generated_html);
-
-    let generated = generated.replace("$TITLE", title);
+    let entries: Vec<IndexNode> = chapters
+        .iter()
+        .map(|entry| IndexNode::new(entry, decks, extension))
+        .collect();
+    let context = minijinja::context! {
+        title,
+        index => minijinja::Value::from_safe_string(generated_html.clone()),
+        entries,
+        tree,
+        ..context
+    };
+    let generated = template.render(
+        &[
+            ("INDEX", &generated_html),
+            ("TITLE", &template::escape_html(title)),
+            ("RAW_TITLE", title),
+        ],
+        context,
+    )?;
 
     output.write_all(generated.as_bytes())?;
 
@@ -422,6 +1251,30 @@ pub fn generate_index(
 mod test {
     use super::*;
 
+    #[test]
+    fn deck_paths() {
+        assert_eq!(deck_url("./cmd-tools.md", "html"), "./cmd-tools.html");
+        assert_eq!(deck_url("markdown.md", "md"), "markdown.md");
+        assert_eq!(
+            deck_url("./part/my%20chapter.md", "html"),
+            "./part/my%20chapter.html"
+        );
+        assert_eq!(
+            deck_path(Path::new("out"), "./part/my%20chapter.md", "html"),
+            Path::new("out/part/my chapter.html")
+        );
+        assert_eq!(percent_decode("100%25 %zz %"), "100% %zz %");
+        assert_eq!(page_depth(Path::new("./part/deeper/deck.html")), 2);
+        assert_eq!(page_depth(Path::new("deck.html")), 0);
+        assert_eq!(relative_url("./reveal.js", 2), "../../reveal.js");
+        assert_eq!(relative_url("agenda.html", 1), "../agenda.html");
+        assert_eq!(relative_url("./reveal.js", 0), "./reveal.js");
+        assert_eq!(
+            relative_url("https://cdn.example.com/reveal.js", 1),
+            "https://cdn.example.com/reveal.js"
+        );
+    }
+
     #[test]
     fn check_index() {
         let index_contents = [
@@ -430,21 +1283,33 @@ mod test {
             IndexEntry::Chapter {
                 title: "Link Title".to_owned(),
                 path: "./test.md".to_owned(),
+                number: SectionNumber(vec![1]),
             },
             IndexEntry::SubHeading("SubHeading 2".to_owned()),
             IndexEntry::Chapter {
                 title: "Link Title 2".to_owned(),
                 path: "./test2.md".to_owned(),
+                number: SectionNumber(vec![2]),
             },
             IndexEntry::Chapter {
                 title: "Link Title 3".to_owned(),
                 path: String::new(),
+                number: SectionNumber(vec![3]),
             },
         ];
         let title = "My Title";
-        let template = "<title>$TITLE</title>\n$INDEX";
+        let template = Template::new("<title>$TITLE</title>\n$INDEX", TemplateSyntax::Auto);
         let mut output = Vec::new();
-        generate_index(&index_contents, &mut output, template, title).unwrap();
+        generate_index(
+            &index_contents,
+            &mut output,
+            &template,
+            title,
+            &BTreeMap::new(),
+            "html",
+            minijinja::Value::UNDEFINED,
+        )
+        .unwrap();
         let output: &str = std::str::from_utf8(&output).unwrap();
         assert_eq!(
             output,
@@ -460,6 +1325,188 @@ mod test {
             <li>Link Title 3</li>\n\
             </ul>\n"
         );
+
+        // Marp decks are Markdown files
+        let template = Template::new(
+            "{{ entries[2].url }} {{ tree[2].url }}",
+            TemplateSyntax::Auto,
+        );
+        let mut output = Vec::new();
+        generate_index(
+            &index_contents,
+            &mut output,
+            &template,
+            title,
+            &BTreeMap::new(),
+            Backend::Marp.extension(),
+            minijinja::Value::UNDEFINED,
+        )
+        .unwrap();
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "./test.md ./test.md");
+    }
+
+    #[test]
+    fn check_nested_index() {
+        let index_contents = [
+            IndexEntry::Heading("Traits & Generics".to_owned()),
+            IndexEntry::Chapter {
+                title: "Traits".to_owned(),
+                path: "./traits.md".to_owned(),
+                number: SectionNumber(vec![1]),
+            },
+            IndexEntry::Chapter {
+                title: "<dyn Trait>".to_owned(),
+                path: "./dyn.md".to_owned(),
+                number: SectionNumber(vec![1, 1]),
+            },
+            IndexEntry::Chapter {
+                title: "Generics".to_owned(),
+                path: "./generics.md".to_owned(),
+                number: SectionNumber(vec![2]),
+            },
+        ];
+        let mut decks = BTreeMap::new();
+        decks.insert(
+            "./traits.md",
+            DeckSummary {
+                slide_count: 3,
+                time: 150,
+                description: Some("Sharing behaviour".to_owned()),
+            },
+        );
+        decks.insert(
+            "./dyn.md",
+            DeckSummary {
+                slide_count: 1,
+                time: 45,
+                description: None,
+            },
+        );
+
+        let template = Template::new("$INDEX", TemplateSyntax::Auto);
+        let mut output = Vec::new();
+        generate_index(
+            &index_contents,
+            &mut output,
+            &template,
+            "My Title",
+            &decks,
+            "html",
+            minijinja::Value::UNDEFINED,
+        )
+        .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "<h1>Traits &amp; Generics</h1>\n\
+            <ul>\n\
+            <li><a href=\"./traits.html\">Traits</a> <small>(3 slides, about 2m 30s)</small>\n\
+            <p>Sharing behaviour</p>\n\
+            <ul>\n\
+            <li><a href=\"./dyn.html\">&lt;dyn Trait&gt;</a> <small>(1 slide, about 45s)</small></li>\n\
+            </ul>\n\
+            </li>\n\
+            <li><a href=\"./generics.html\">Generics</a></li>\n\
+            </ul>\n"
+        );
+
+        let template = Template::new(
+            "{% for entry in tree recursive %}{{ entry.number }}{{ entry.title }}/{{ entry.slide_count }}({{ loop(entry.children) }}) {% endfor %}",
+            TemplateSyntax::Auto,
+        );
+        let mut output = Vec::new();
+        generate_index(
+            &index_contents,
+            &mut output,
+            &template,
+            "My Title",
+            &decks,
+            "html",
+            minijinja::Value::UNDEFINED,
+        )
+        .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&output).unwrap(),
+            "Traits &amp; Generics/() 1.Traits/3(1.1.&lt;dyn Trait&gt;/1() ) 2.Generics/() "
+        );
+    }
+
+    #[test]
+    fn escaped_deck_titles() {
+        let dir = tempfile::tempdir().unwrap();
+        let in_path = dir.path().join("smart-pointers.md");
+        std::fs::write(&in_path, "# `Box<T>` & `Rc<T>`\n").unwrap();
+        let out_path = dir.path().join("smart-pointers.html");
+        let diagrams = diagram::Registry::from_config(&Config::default()).unwrap();
+        let chapter = Chapter::load(
+            "Box<T> & Rc<T>",
+            "./smart-pointers.md",
+            in_path,
+            &diagrams.languages(),
+        )
+        .unwrap();
+        let deck = |template: &str| {
+            generate_deck(
+                &chapter,
+                &out_path,
+                &Template::new(template, TemplateSyntax::Auto),
+                Backend::Reveal,
+                &diagrams,
+                &links::Links::default(),
+                &config::TimingConfig::default(),
+                minijinja::Value::UNDEFINED,
+            )
+            .unwrap();
+            std::fs::read_to_string(&out_path).unwrap()
+        };
+        assert_eq!(
+            deck("<title>$TITLE</title>\n$RAW_TITLE\n$CONTENT"),
+            "<title>Box&lt;T&gt; &amp; Rc&lt;T&gt;</title>\nBox<T> & Rc<T>\n# `Box<T>` & `Rc<T>`\n"
+        );
+        assert_eq!(
+            deck("<title>{{ title }}</title>\n{{ title|safe }}\n{% for slide in slides %}<h2>{{ slide.title }}</h2>{% endfor %}"),
+            "<title>Box&lt;T&gt; &amp; Rc&lt;T&gt;</title>\nBox<T> & Rc<T>\n<h2>`Box&lt;T&gt;` &amp; `Rc&lt;T&gt;`</h2>"
+        );
+    }
+
+    #[test]
+    fn failing_diagram() {
+        if !cfg!(unix) {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let in_path = dir.path().join("broken.md");
+        std::fs::write(
+            &in_path,
+            "# One\n\n# Two\n\n```broken process alt=\"Flow\"\na -> b\n```\n",
+        )
+        .unwrap();
+        let mut diagrams = diagram::Registry::default();
+        diagrams.register(
+            "broken",
+            Box::new(diagram::CommandRenderer::new("false", Vec::new())),
+        );
+        let chapter =
+            Chapter::load("Broken", "./broken.md", in_path, &diagrams.languages()).unwrap();
+        let result = generate_deck(
+            &chapter,
+            &dir.path().join("broken.html"),
+            &Template::new("$CONTENT", TemplateSyntax::Auto),
+            Backend::Reveal,
+            &diagrams,
+            &links::Links::default(),
+            &config::TimingConfig::default(),
+            minijinja::Value::UNDEFINED,
+        );
+        let Err(error @ Error::DiagramFailed(_, _, 2, _)) = result else {
+            panic!("expected a diagram error, got {:?}", result);
+        };
+        let message = error.to_string();
+        assert!(
+            message.contains("\"Flow\" diagram on slide 2 of"),
+            "{}",
+            message
+        );
+        assert!(message.contains("broken.md"), "{}", message);
     }
 
     #[test]
@@ -494,32 +1541,88 @@ mod test {
                 IndexEntry::SubHeading("Subheading 1.1".to_string()),
                 IndexEntry::Chapter {
                     title: "Link 1".to_string(),
-                    path: "./link1.md".to_string()
+                    path: "./link1.md".to_string(),
+                    number: SectionNumber(vec![1]),
                 },
                 IndexEntry::Chapter {
                     title: "Link 2".to_string(),
-                    path: "./link2.md".to_string()
+                    path: "./link2.md".to_string(),
+                    number: SectionNumber(vec![2]),
                 },
                 IndexEntry::SubHeading("Subheading 1.2".to_string()),
                 IndexEntry::Chapter {
                     title: "Link 3".to_string(),
-                    path: "./link3.md".to_string()
+                    path: "./link3.md".to_string(),
+                    number: SectionNumber(vec![3]),
                 },
                 IndexEntry::Chapter {
                     title: "Link 4".to_string(),
-                    path: "./link4.md".to_string()
+                    path: "./link4.md".to_string(),
+                    number: SectionNumber(vec![4]),
                 },
                 IndexEntry::Heading("Heading 2".to_string()),
                 IndexEntry::SubHeading("Subheading 2.1".to_string()),
                 IndexEntry::Chapter {
                     title: "Link 5".to_string(),
-                    path: "./link5.md".to_string()
+                    path: "./link5.md".to_string(),
+                    number: SectionNumber(vec![5]),
                 },
                 IndexEntry::Chapter {
                     title: "Link 6".to_string(),
                     path: String::new(),
+                    number: SectionNumber(vec![6]),
                 },
             ]
         );
     }
+
+    #[test]
+    fn check_nested_book() {
+        let summary_src = "\
+        # Part 1\n\
+        \n\
+        - [A](./a.md)\n\
+        \x20   - [A1](./a1.md)\n\
+        \x20   - [A2](./a2.md)\n\
+        - [B](./b.md)\n\
+        \n\
+        # Part 2\n\
+        \n\
+        - [C](./c.md)\n\
+        ";
+        let index_entries = load_book(summary_src).unwrap();
+        let numbers: Vec<String> = index_entries
+            .iter()
+            .filter_map(|entry| match entry {
+                IndexEntry::Chapter { number, .. } => Some(number.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(numbers, ["1.", "1.1.", "1.2.", "2.", "3."]);
+    }
+
+    #[test]
+    fn check_book_titles() {
+        let summary_src = "\
+        - [Box<T> & Rc<T>](./box.md)\n\
+        - [Vec&lt;T&gt;](./vec.md)\n\
+        - [`Pin`](./pin.md)\n\
+        ";
+        let index_entries = load_book(summary_src).unwrap();
+        let titles: Vec<&str> = index_entries
+            .iter()
+            .filter_map(|entry| match entry {
+                IndexEntry::Chapter { title, .. } => Some(title.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(titles, ["Box<T> & Rc<T>", "Vec<T>", "Pin"]);
+    }
+
+    #[test]
+    fn check_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951868740), "2000-02-29");
+        assert_eq!(format_date(1771545600), "2026-02-20");
+    }
 }
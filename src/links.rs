@@ -0,0 +1,416 @@
+//! Rewriting links between chapters, so they point at the generated decks
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::assets::normalise;
+use crate::slides::{Block, Slide};
+use crate::{chapter_file, percent_decode, slides, Backend, Book, Chapter, Error};
+
+/// The anchors in every chapter of a book, so links to them can be rewritten
+#[derive(Debug, Default)]
+pub struct Links {
+    /// The book's `src` folder
+    src_dir: PathBuf,
+    /// The chapters we generate decks for, by their path relative to the
+    /// `src` folder, with the anchors of the headings on each slide and how
+    /// many remark.js increments it has
+    chapters: BTreeMap<PathBuf, Vec<(Vec<String>, usize)>>,
+}
+
+impl Links {
+    /// Find the anchors in some chapters which have already been loaded.
+    pub fn for_chapters(src_dir: &Path, chapters: &[Chapter]) -> Links {
+        let chapters = chapters
+            .iter()
+            .filter_map(|chapter| {
+                let path = normalise(&chapter_file(chapter.path))?;
+                Some((path, chapter.slides.iter().map(slide_links).collect()))
+            })
+            .collect();
+        Links {
+            src_dir: src_dir.to_owned(),
+            chapters,
+        }
+    }
+
+    /// Find the anchors in every chapter of a book.
+    ///
+    /// Chapters whose files are missing are left out.
+    pub fn for_book(book: &Book, diagram_languages: &[&str]) -> Result<Links, Error> {
+        let mut chapters = BTreeMap::new();
+        for (_title, path) in book.chapters() {
+            let chapter_path = book.chapter_path(path);
+            if !chapter_path.exists() {
+                // Building will fail when it gets to this chapter, and
+                // checking will report it
+                continue;
+            }
+            let content = std::fs::read_to_string(chapter_path)?;
+            let (_front_matter, content) = slides::front_matter(&content)?;
+            let anchors = slides::split(content, diagram_languages)
+                .iter()
+                .map(slide_links)
+                .collect();
+            if let Some(path) = normalise(&chapter_file(path)) {
+                chapters.insert(path, anchors);
+            }
+        }
+        Ok(Links {
+            src_dir: book.src_dir.clone(),
+            chapters,
+        })
+    }
+
+    /// Rewrite the links to other chapters in some Markdown from the chapter
+    /// at `in_path`.
+    ///
+    /// Links to a chapter's `.md` file are changed to point at its deck, and
+    /// an anchor is changed to the number of the slide containing that
+    /// heading. Links to chapters we don't have a deck for are left alone.
+    pub fn rewrite(&self, markdown: &str, in_path: &Path, backend: Backend) -> String {
+        let chapter = in_path.strip_prefix(&self.src_dir).unwrap_or(in_path);
+        let chapter_dir = chapter.parent().unwrap_or(Path::new(""));
+        let mut output = String::with_capacity(markdown.len());
+        let mut done = 0;
+        for (range, destination) in link_destinations(markdown) {
+            let Some(new_destination) = self.rewrite_url(&destination, chapter_dir, backend) else {
+                continue;
+            };
+            let Some(range) = range else {
+                log::warn!(
+                    "Link to {:?} in {} not rewritten, as it couldn't be found in the Markdown",
+                    destination,
+                    in_path.display()
+                );
+                continue;
+            };
+            if range.start < done {
+                continue;
+            }
+            output.push_str(&markdown[done..range.start]);
+            output.push_str(&new_destination);
+            done = range.end;
+        }
+        output.push_str(&markdown[done..]);
+        output
+    }
+
+    /// Rewrite the links to other chapters in the slides of the chapter at
+    /// `in_path`, in the same way as [`Links::rewrite`], including those in
+    /// speaker notes.
+    pub fn rewrite_slides(&self, slides: &[Slide], in_path: &Path, backend: Backend) -> Vec<Slide> {
+        slides
+            .iter()
+            .map(|slide| Slide {
+                blocks: slide
+                    .blocks
+                    .iter()
+                    .map(|block| match block {
+                        Block::Markdown(text) => {
+                            Block::Markdown(self.rewrite(text, in_path, backend))
+                        }
+                        Block::Diagram(diagram) => Block::Diagram(diagram.clone()),
+                    })
+                    .collect(),
+                notes: slide
+                    .notes
+                    .as_deref()
+                    .map(|notes| self.rewrite(notes, in_path, backend)),
+                ..slide.clone()
+            })
+            .collect()
+    }
+
+    /// Find links to chapters which we don't have decks for, and links to
+    /// anchors which don't exist, in some Markdown from the chapter at
+    /// `in_path`.
+    pub fn broken_links(&self, markdown: &str, in_path: &Path) -> Vec<String> {
+        let chapter = in_path.strip_prefix(&self.src_dir).unwrap_or(in_path);
+        let chapter_dir = chapter.parent().unwrap_or(Path::new(""));
+        let mut problems = Vec::new();
+        for (_range, url) in link_destinations(markdown) {
+            let (path, anchor) = match url.split_once('#') {
+                Some((path, anchor)) => (path, Some(anchor)),
+                None => (url.as_str(), None),
+            };
+            let target = if path.is_empty() {
+                normalise(chapter)
+            } else if path.ends_with(".md") && !path.contains(':') && !path.starts_with('/') {
+                normalise(&chapter_dir.join(percent_decode(path)))
+            } else {
+                continue;
+            };
+            let Some(slides) = target.and_then(|target| self.chapters.get(&target)) else {
+                problems.push(format!(
+                    "links to {:?}, which isn't a chapter with slides",
+                    url
+                ));
+                continue;
+            };
+            if let Some(anchor) = anchor {
+                if !slides
+                    .iter()
+                    .flat_map(|(anchors, _)| anchors)
+                    .any(|a| a == anchor)
+                {
+                    problems.push(format!(
+                        "links to {:?}, but there's no heading with that anchor",
+                        url
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Work out the new URL for a link, if it is to another chapter.
+    fn rewrite_url(&self, url: &str, chapter_dir: &Path, backend: Backend) -> Option<String> {
+        let (path, anchor) = match url.split_once('#') {
+            Some((path, anchor)) => (path, Some(anchor)),
+            None => (url, None),
+        };
+        let stem = path.strip_suffix(".md")?;
+        if path.contains(':') || path.starts_with('/') {
+            return None;
+        }
+        let target = normalise(&chapter_dir.join(percent_decode(path)))?;
+        let Some(slides) = self.chapters.get(&target) else {
+            log::warn!(
+                "Link to {:?} not rewritten, as that chapter was skipped or isn't in SUMMARY.md",
+                url
+            );
+            return None;
+        };
+        let mut new_url = format!("{}.{}", stem, backend.extension());
+        if let Some(anchor) = anchor {
+            match slides
+                .iter()
+                .position(|(anchors, _)| anchors.iter().any(|a| a == anchor))
+            {
+                Some(idx) => {
+                    let increments = slides[..idx].iter().map(|(_, increments)| increments).sum();
+                    new_url.push_str(&backend.slide_anchor(idx, increments));
+                }
+                None => log::warn!("No heading with the anchor {:?} in {}", anchor, path),
+            }
+        }
+        Some(new_url)
+    }
+}
+
+/// Find the destinations of the inline links and reference definitions in
+/// some Markdown, with where each destination is written in the Markdown.
+///
+/// The location is `None` if the destination couldn't be found.
+fn link_destinations(markdown: &str) -> Vec<(Option<Range<usize>>, String)> {
+    let parser = pulldown_cmark::Parser::new(markdown);
+    let mut destinations: Vec<(Option<Range<usize>>, String)> = parser
+        .reference_definitions()
+        .iter()
+        .map(|(_label, definition)| {
+            let range = definition_destination(markdown, definition.span.clone());
+            (range, definition.dest.to_string())
+        })
+        .collect();
+    // The inline link we're in, and where its text ends so far
+    let mut link: Option<(String, usize)> = None;
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link {
+                link_type: pulldown_cmark::LinkType::Inline,
+                dest_url,
+                ..
+            }) => {
+                // Skip the `[`
+                link = Some((dest_url.into_string(), range.start + 1));
+            }
+            pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Link) => {
+                if let Some((destination, text_end)) = link.take() {
+                    let range = inline_destination(markdown, text_end..range.end);
+                    destinations.push((range, destination));
+                }
+            }
+            _ => {
+                if let Some((_, text_end)) = link.as_mut() {
+                    *text_end = (*text_end).max(range.end);
+                }
+            }
+        }
+    }
+    destinations.sort_by_key(|(range, _)| range.as_ref().map(|range| range.start));
+    destinations
+}
+
+/// Find the destination of an inline link, given the part of the Markdown
+/// from the end of the link's text, like `](chapter.md "Title")`.
+fn inline_destination(markdown: &str, rest: Range<usize>) -> Option<Range<usize>> {
+    let start = rest.start + markdown[rest.clone()].find("](")? + 2;
+    destination_at(markdown, start..rest.end)
+}
+
+/// Find the destination of a reference definition, given the Markdown
+/// of the whole definition, like `[label]: chapter.md "Title"`.
+fn definition_destination(markdown: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let definition = &markdown[span.clone()];
+    let mut escaped = false;
+    let label_end = definition.char_indices().skip(1).find_map(|(idx, c)| {
+        let found = c == ']' && !escaped;
+        escaped = c == '\\' && !escaped;
+        found.then_some(idx)
+    })?;
+    let start = span.start + label_end + 1;
+    if !markdown[start..].starts_with(':') {
+        return None;
+    }
+    destination_at(markdown, start + 1..span.end)
+}
+
+/// Find a link destination at the start of some Markdown, after any
+/// whitespace, leaving out the `<` and `>` around it if it has them.
+fn destination_at(markdown: &str, rest: Range<usize>) -> Option<Range<usize>> {
+    let text = &markdown[rest.clone()];
+    let start = rest.start + (text.len() - text.trim_start().len());
+    let text = &markdown[start..rest.end];
+    if let Some(text) = text.strip_prefix('<') {
+        let end = text.find('>')?;
+        return Some(start + 1..start + 1 + end);
+    }
+    // Otherwise it ends at whitespace, or a `)` which doesn't match a `(`
+    let mut depth = 0;
+    let mut escaped = false;
+    for (idx, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(start..start + idx),
+            ')' => depth -= 1,
+            c if c.is_whitespace() => return Some(start..start + idx),
+            _ => {}
+        }
+    }
+    Some(start..rest.end)
+}
+
+/// The anchors of all the headings on a slide, and how many remark.js
+/// increments it has.
+fn slide_links(slide: &Slide) -> (Vec<String>, usize) {
+    (slide_anchors(slide), slide.increments())
+}
+
+/// The anchors of all the headings on a slide.
+fn slide_anchors(slide: &Slide) -> Vec<String> {
+    let mut anchors = Vec::new();
+    for block in slide.blocks.iter() {
+        let Block::Markdown(text) = block else {
+            continue;
+        };
+        let parser = pulldown_cmark::Parser::new_ext(
+            text,
+            pulldown_cmark::Options::ENABLE_HEADING_ATTRIBUTES,
+        );
+        let mut heading: Option<String> = None;
+        for event in parser {
+            match event {
+                pulldown_cmark::Event::Start(pulldown_cmark::Tag::Heading { id, .. }) => {
+                    if let Some(id) = id {
+                        anchors.push(id.into_string());
+                    } else {
+                        heading = Some(String::new());
+                    }
+                }
+                pulldown_cmark::Event::Text(text) | pulldown_cmark::Event::Code(text) => {
+                    if let Some(heading) = heading.as_mut() {
+                        heading.push_str(&text);
+                    }
+                }
+                pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Heading(_)) => {
+                    if let Some(heading) = heading.take() {
+                        anchors.push(anchor_for(&heading));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    anchors
+}
+
+/// The anchor mdbook gives a heading.
+fn anchor_for(heading: &str) -> String {
+    heading
+        .chars()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' {
+                Some(c.to_ascii_lowercase())
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rewrite() {
+        let mut chapters = BTreeMap::new();
+        chapters.insert(
+            PathBuf::from("traits.md"),
+            vec![
+                // The first slide is shown in two steps by remark.js
+                (vec!["traits".to_owned()], 1),
+                (vec!["generic-traits".to_owned(), "custom".to_owned()], 0),
+            ],
+        );
+        let links = Links {
+            src_dir: PathBuf::from("src"),
+            chapters,
+        };
+        let markdown = "See [traits](../traits.md#generic-traits), [this](../traits.md#custom \"Title\"),\n[the docs](https://doc.rust-lang.org/) and [skipped](./skipped.md).\n\n[ref]: ../traits.md\n";
+        assert_eq!(
+            links.rewrite(markdown, Path::new("src/part/chapter.md"), Backend::Reveal),
+            "See [traits](../traits.html#/1), [this](../traits.html#/1 \"Title\"),\n[the docs](https://doc.rust-lang.org/) and [skipped](./skipped.md).\n\n[ref]: ../traits.html\n"
+        );
+
+        // Only the destination is changed, not a title mentioning it
+        let markdown = "[x](../traits.md \"see ../traits.md\") [`y` [z]](<../traits.md>) [e](../tr&#97;its.md)\n\n[ref\\]]: ../traits.md 'also ../traits.md'\n";
+        assert_eq!(
+            links.rewrite(markdown, Path::new("src/part/chapter.md"), Backend::Reveal),
+            "[x](../traits.html \"see ../traits.md\") [`y` [z]](<../traits.html>) [e](../traits.html)\n\n[ref\\]]: ../traits.html 'also ../traits.md'\n"
+        );
+
+        let slides = slides::split(
+            "# Slide\n\nSee [traits](../traits.md).\n\nNote: and [custom](../traits.md#custom)\n",
+            &[],
+        );
+        let rewritten =
+            links.rewrite_slides(&slides, Path::new("src/part/chapter.md"), Backend::Remark);
+        assert_eq!(
+            rewritten[0].blocks,
+            [Block::Markdown(
+                "# Slide\n\nSee [traits](../traits.html).\n\n".to_owned()
+            )]
+        );
+        assert_eq!(
+            rewritten[0].notes.as_deref(),
+            Some("and [custom](../traits.html#3)\n")
+        );
+    }
+
+    #[test]
+    fn anchors() {
+        let slides = slides::split(
+            "# Traits\n\n## Generic `Trait`s\n\n### Custom heading {#custom}\n\n```rust\n# not a heading\n```\n",
+            &[],
+        );
+        let anchors: Vec<_> = slides.iter().map(slide_anchors).collect();
+        assert_eq!(anchors, [vec!["traits"], vec!["generic-traits", "custom"]]);
+    }
+}
@@ -0,0 +1,326 @@
+//! Checking slides for walls of text, and other things which don't present well
+//!
+//! The limits, and how seriously each rule is taken, are set in the `[lint]`
+//! table of `mdslides.toml`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::config::LintConfig;
+use crate::slides::{Block, Slide};
+use crate::{diagram, Book, Error};
+
+/// The things we check slides for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// More lines of text than `max_lines`
+    TooManyLines,
+    /// More bullet points than `max_bullets`
+    TooManyBullets,
+    /// A code block with more lines than `max_code_lines`
+    TooManyCodeLines,
+    /// A line of text longer than `max_line_length` characters
+    LongLine,
+    /// A line of code longer than `max_code_width` characters
+    WideCode,
+    /// A slide with nothing on it
+    EmptySlide,
+    /// A slide which doesn't start with a heading
+    NoHeading,
+    /// A slide with the same title as an earlier one in the chapter
+    DuplicateTitle,
+}
+
+impl Rule {
+    /// The name of the rule, as used in `mdslides.toml`
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::TooManyLines => "too-many-lines",
+            Rule::TooManyBullets => "too-many-bullets",
+            Rule::TooManyCodeLines => "too-many-code-lines",
+            Rule::LongLine => "long-line",
+            Rule::WideCode => "wide-code",
+            Rule::EmptySlide => "empty-slide",
+            Rule::NoHeading => "no-heading",
+            Rule::DuplicateTitle => "duplicate-title",
+        }
+    }
+}
+
+/// How seriously to take a rule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Don't check this rule
+    Off,
+    /// Report it, but don't fail
+    #[default]
+    Warn,
+    /// Report it, and make `mdslides lint` fail
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Off => "off",
+            Severity::Warn => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A problem found on a slide
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// The chapter's path in `SUMMARY.md`
+    pub chapter: String,
+    /// The number of the slide within the chapter, counting from one
+    pub slide: usize,
+    /// The rule which was broken
+    pub rule: Rule,
+    /// How serious it is
+    pub severity: Severity,
+    /// What the problem is
+    pub message: String,
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} slide {}: {} [{}]",
+            self.severity,
+            self.chapter,
+            self.slide,
+            self.message,
+            self.rule.name()
+        )
+    }
+}
+
+/// Check the slides of every chapter in an mdbook.
+///
+/// * `mdbook_path` - the location of the mdbook's `book.toml`. Assumed to be
+///   the current directory if not given.
+pub fn lint(mdbook_path: Option<&Path>) -> Result<Vec<Lint>, Error> {
+    let book = Book::load(mdbook_path)?;
+    let diagrams = diagram::Registry::from_config(&book.config)?;
+    let mut lints = Vec::new();
+    for chapter in book.load_chapters(&diagrams.languages())? {
+        lints.extend(lint_slides(
+            chapter.path,
+            &chapter.slides,
+            &book.config.lint,
+        ));
+    }
+    Ok(lints)
+}
+
+/// Check the slides of one chapter.
+pub fn lint_slides(chapter: &str, slides: &[Slide], config: &LintConfig) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut titles: BTreeMap<&str, usize> = BTreeMap::new();
+    for (idx, slide) in slides.iter().enumerate() {
+        let mut report = |rule: Rule, message: String| {
+            let severity = config.severity(rule);
+            if severity != Severity::Off {
+                lints.push(Lint {
+                    chapter: chapter.to_owned(),
+                    slide: idx + 1,
+                    rule,
+                    severity,
+                    message,
+                });
+            }
+        };
+
+        let stats = SlideStats::new(slide);
+        let has_diagrams = slide.diagrams().next().is_some();
+        if stats.lines == 0 && !has_diagrams {
+            // A chapter which is empty, or only has front matter, has one
+            // empty slide, which isn't worth reporting
+            if slides.len() > 1 {
+                report(Rule::EmptySlide, "the slide is empty".to_owned());
+            }
+            continue;
+        }
+        match slide.title.as_deref() {
+            None => report(
+                Rule::NoHeading,
+                "the slide doesn't start with a heading".to_owned(),
+            ),
+            Some(title) => {
+                if let Some(first) = titles.get(title) {
+                    report(
+                        Rule::DuplicateTitle,
+                        format!("slide {} has the same title, {:?}", first, title),
+                    );
+                } else {
+                    titles.insert(title, idx + 1);
+                }
+            }
+        }
+        if stats.lines > config.max_lines {
+            report(
+                Rule::TooManyLines,
+                format!(
+                    "{} lines is more than the limit of {}",
+                    stats.lines, config.max_lines
+                ),
+            );
+        }
+        if stats.bullets > config.max_bullets {
+            report(
+                Rule::TooManyBullets,
+                format!(
+                    "{} bullet points is more than the limit of {}",
+                    stats.bullets, config.max_bullets
+                ),
+            );
+        }
+        if stats.longest_code_block > config.max_code_lines {
+            report(
+                Rule::TooManyCodeLines,
+                format!(
+                    "a code block of {} lines is longer than the limit of {}",
+                    stats.longest_code_block, config.max_code_lines
+                ),
+            );
+        }
+        if stats.longest_line > config.max_line_length {
+            report(
+                Rule::LongLine,
+                format!(
+                    "a line of {} characters is longer than the limit of {}",
+                    stats.longest_line, config.max_line_length
+                ),
+            );
+        }
+        if stats.widest_code > config.max_code_width {
+            report(
+                Rule::WideCode,
+                format!(
+                    "a line of code of {} characters is wider than the limit of {}",
+                    stats.widest_code, config.max_code_width
+                ),
+            );
+        }
+    }
+    lints
+}
+
+/// Measurements of the Markdown on a slide
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SlideStats {
+    /// Non-blank lines, including code
+    lines: usize,
+    /// Bullet points and numbered list items
+    bullets: usize,
+    /// The length of the longest code block
+    longest_code_block: usize,
+    /// The length of the longest line which isn't code
+    longest_line: usize,
+    /// The length of the longest line of code
+    widest_code: usize,
+}
+
+impl SlideStats {
+    fn new(slide: &Slide) -> SlideStats {
+        let mut stats = SlideStats::default();
+        let mut code_block: Option<usize> = None;
+        for block in slide.blocks.iter() {
+            let Block::Markdown(text) = block else {
+                continue;
+            };
+            for line in text.lines() {
+                let trimmed = line.trim();
+                let length = line.chars().count();
+                if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                    code_block = match code_block {
+                        Some(code_lines) => {
+                            stats.longest_code_block = stats.longest_code_block.max(code_lines);
+                            None
+                        }
+                        None => Some(0),
+                    };
+                    continue;
+                }
+                if let Some(code_lines) = code_block.as_mut() {
+                    *code_lines += 1;
+                    stats.lines += 1;
+                    stats.widest_code = stats.widest_code.max(length);
+                    continue;
+                }
+                if trimmed.is_empty() {
+                    continue;
+                }
+                stats.lines += 1;
+                stats.longest_line = stats.longest_line.max(length);
+                if is_list_item(trimmed) {
+                    stats.bullets += 1;
+                }
+            }
+        }
+        if let Some(code_lines) = code_block {
+            stats.longest_code_block = stats.longest_code_block.max(code_lines);
+        }
+        stats
+    }
+}
+
+/// Does this (trimmed) line start a list item?
+fn is_list_item(line: &str) -> bool {
+    if ["- ", "* ", "+ "]
+        .iter()
+        .any(|bullet| line.starts_with(bullet))
+    {
+        return true;
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    digits > 0 && (line[digits..].starts_with(". ") || line[digits..].starts_with(") "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::slides;
+
+    #[test]
+    fn lints() {
+        let slides = slides::split(
+            "# Intro\n\n- one\n- two\n- three\n\n---\n\n---\n\nNo heading here, and quite a long line\n\n# Intro\n\n```rust\nfn main() { println!(\"Hello, world!\"); }\n```\n",
+            &[],
+        );
+        let mut config: LintConfig = toml::from_str(
+            r#"
+            max_bullets = 2
+            max_line_length = 20
+            max_code_width = 30
+            [rules]
+            empty-slide = "error"
+            long-line = "off"
+            "#,
+        )
+        .unwrap();
+        let lints: Vec<String> = lint_slides("./intro.md", &slides, &config)
+            .iter()
+            .map(Lint::to_string)
+            .collect();
+        assert_eq!(
+            lints,
+            [
+                "warning: ./intro.md slide 1: 3 bullet points is more than the limit of 2 [too-many-bullets]",
+                "error: ./intro.md slide 2: the slide is empty [empty-slide]",
+                "warning: ./intro.md slide 3: the slide doesn't start with a heading [no-heading]",
+                "warning: ./intro.md slide 4: slide 1 has the same title, \"Intro\" [duplicate-title]",
+                "warning: ./intro.md slide 4: a line of code of 40 characters is wider than the limit of 30 [wide-code]",
+            ]
+        );
+
+        config.rules.insert(Rule::LongLine, Severity::Warn);
+        let lints = lint_slides("./intro.md", &slides, &config);
+        assert!(lints.iter().any(|lint| lint.rule == Rule::LongLine));
+    }
+}
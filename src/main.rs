@@ -2,9 +2,9 @@
 //!
 //! Written by Jonathan Pallant at Ferrous Systems
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 static ABOUT_TEXT: &str = concat!(
     env!("CARGO_PKG_DESCRIPTION"),
@@ -26,23 +26,114 @@ You are running version "#,
 
 /// Command line arguments for this program.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = ABOUT_TEXT)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = ABOUT_TEXT,
+    args_conflicts_with_subcommands = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    build: BuildArgs,
+}
+
+/// Things this program can do, other than building slides.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a JSON description of the book and its slide decks
+    Dump {
+        /// The mdbook to process
+        #[arg(long)]
+        mdbook_path: Option<PathBuf>,
+
+        /// Write the JSON to this file, instead of to stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Set up an mdbook for slides, with templates and an example chapter
+    Init {
+        /// The mdbook to set up, which is created if it doesn't exist
+        #[arg(long)]
+        mdbook_path: Option<PathBuf>,
+
+        /// Overwrite any existing templates, example chapter or mdslides.toml
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check the book for missing files and broken links
+    Check {
+        /// The mdbook to check
+        #[arg(long)]
+        mdbook_path: Option<PathBuf>,
+
+        /// Also check that external URLs can be fetched
+        #[arg(long)]
+        urls: bool,
+    },
+    /// Check the slides for walls of text and other problems
+    Lint {
+        /// The mdbook to check
+        #[arg(long)]
+        mdbook_path: Option<PathBuf>,
+    },
+    /// Manage the cache of rendered diagrams
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+/// Things to do with the diagram cache.
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Delete all the cached diagrams
+    Clean {
+        /// The mdbook whose cache should be deleted
+        #[arg(long)]
+        mdbook_path: Option<PathBuf>,
+    },
+}
+
+/// Arguments for building slides.
+///
+/// These override any settings in the book's `mdslides.toml` file.
+#[derive(clap::Args, Debug)]
+struct BuildArgs {
     /// The mdbook to process
     #[arg(long)]
     mdbook_path: Option<PathBuf>,
 
     /// The output directory
     #[arg(long)]
-    output_dir: PathBuf,
+    output_dir: Option<PathBuf>,
 
     /// The HTML Template for the slides.
     #[arg(long)]
-    template: PathBuf,
+    template: Option<PathBuf>,
 
     /// The HTML Template for the index.
     #[arg(long)]
     index_template: Option<PathBuf>,
+
+    /// The syntax of the templates [default: auto]
+    #[arg(long, value_enum)]
+    template_syntax: Option<mdslides::TemplateSyntax>,
+
+    /// The slide framework to generate decks for [default: reveal]
+    #[arg(long, value_enum)]
+    backend: Option<mdslides::Backend>,
+
+    /// The reveal.js theme for the built-in template [default: black]
+    #[arg(long)]
+    theme: Option<String>,
+
+    /// How to put rendered diagrams into the slides [default: inline]
+    #[arg(long, value_enum)]
+    diagram_format: Option<mdslides::DiagramFormat>,
 }
 
 fn main() -> Result<(), mdslides::Error> {
@@ -50,19 +141,74 @@ fn main() -> Result<(), mdslides::Error> {
     let args = Args::parse();
     log::debug!("Args: {:?}", args);
 
-    log::info!("Loading slide template: {}", args.template.display());
-    let slide_template_string = std::fs::read_to_string(&args.template)?;
+    match args.command {
+        Some(Command::Dump {
+            mdbook_path,
+            output,
+        }) => dump(mdbook_path.as_deref(), output.as_deref()),
+        Some(Command::Init { mdbook_path, force }) => mdslides::init(mdbook_path.as_deref(), force),
+        Some(Command::Check { mdbook_path, urls }) => check(mdbook_path.as_deref(), urls),
+        Some(Command::Lint { mdbook_path }) => lint(mdbook_path.as_deref()),
+        Some(Command::Cache {
+            command: CacheCommand::Clean { mdbook_path },
+        }) => mdslides::clean_cache(mdbook_path.as_deref()),
+        None => build(args.build),
+    }
+}
 
-    let mut index_template_string = None;
-    if let Some(index_template_path) = args.index_template {
-        log::info!("Using index template: {}", index_template_path.display());
-        index_template_string = Some(std::fs::read_to_string(index_template_path)?);
+/// Build the slide decks, and optionally the index.
+fn build(args: BuildArgs) -> Result<(), mdslides::Error> {
+    let options = mdslides::BuildOptions {
+        output_dir: args.output_dir,
+        template: args.template,
+        index_template: args.index_template,
+        backend: args.backend,
+        template_syntax: args.template_syntax,
+        theme: args.theme,
+        diagram_format: args.diagram_format,
+    };
+    mdslides::build(args.mdbook_path.as_deref(), &options)
+}
+
+/// Check the book, printing any problems found.
+fn check(mdbook_path: Option<&Path>, urls: bool) -> Result<(), mdslides::Error> {
+    let options = mdslides::CheckOptions { urls };
+    let problems = mdslides::check(mdbook_path, &options)?;
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(mdslides::Error::CheckFailed(problems.len()))
     }
+}
+
+/// Lint the slides, printing anything found.
+///
+/// Only fails if a rule with the `error` severity was broken.
+fn lint(mdbook_path: Option<&Path>) -> Result<(), mdslides::Error> {
+    let lints = mdslides::lint(mdbook_path)?;
+    for lint in lints.iter() {
+        println!("{}", lint);
+    }
+    let errors = lints
+        .iter()
+        .filter(|lint| lint.severity == mdslides::Severity::Error)
+        .count();
+    if errors == 0 {
+        Ok(())
+    } else {
+        Err(mdslides::Error::LintFailed(errors))
+    }
+}
 
-    mdslides::run(
-        args.mdbook_path.as_deref(),
-        &args.output_dir,
-        &slide_template_string,
-        index_template_string.as_deref(),
-    )
+/// Dump the book to a file, or to stdout.
+fn dump(mdbook_path: Option<&Path>, output: Option<&Path>) -> Result<(), mdslides::Error> {
+    if let Some(output) = output {
+        let mut file = std::fs::File::create(output)?;
+        mdslides::dump(mdbook_path, &mut file)
+    } else {
+        mdslides::dump(mdbook_path, &mut std::io::stdout().lock())
+    }
 }
@@ -0,0 +1,168 @@
+//! A search index of every slide, so slides can be found from the index page
+//!
+//! The index is written as JSON, and also as a script which sets
+//! `window.mdslidesSearchIndex`, because browsers won't let a page opened
+//! from disk fetch the JSON file. The search widget reads the script.
+
+use std::path::Path;
+
+use crate::slides::{Block, Slide};
+use crate::{Backend, Error};
+
+/// The search index, as JSON, in the output directory
+pub const SEARCH_INDEX_JSON: &str = "search-index.json";
+
+/// The search index, as a script, in the output directory
+pub const SEARCH_INDEX_JS: &str = "search-index.js";
+
+/// The search widget, in the output directory
+pub const SEARCH_JS: &str = "search.js";
+
+/// The source of the search widget
+const SEARCH_WIDGET: &str = include_str!("templates/search.js");
+
+/// One slide in the search index
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SearchEntry {
+    /// The title of the chapter
+    pub deck: String,
+    /// The URL of the slide, relative to the output directory
+    pub url: String,
+    /// The number of the slide within the deck, counting from one
+    pub slide: usize,
+    /// The slide's heading, if it has one
+    pub heading: Option<String>,
+    /// The rest of the slide's text, including code, as plain text
+    pub text: String,
+}
+
+/// The slides of every deck in a book
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    entries: Vec<SearchEntry>,
+}
+
+impl SearchIndex {
+    /// Add the slides of a chapter's deck.
+    ///
+    /// * `deck_url` - the URL of the deck, relative to the output directory
+    pub fn add_deck(&mut self, title: &str, deck_url: &str, slides: &[Slide], backend: Backend) {
+        let mut increments = 0;
+        for (idx, slide) in slides.iter().enumerate() {
+            self.entries.push(SearchEntry {
+                deck: title.to_owned(),
+                url: format!("{}{}", deck_url, backend.slide_anchor(idx, increments)),
+                slide: idx + 1,
+                heading: slide.title.clone(),
+                text: slide_text(slide),
+            });
+            increments += slide.increments();
+        }
+    }
+
+    /// The slides in the index, in order
+    pub fn entries(&self) -> &[SearchEntry] {
+        &self.entries
+    }
+
+    /// Write the index, and the search widget, into the output directory.
+    pub fn write(&self, output_dir: &Path) -> Result<(), Error> {
+        log::info!("Writing search index of {} slides", self.entries.len());
+        let json = serde_json::to_string(&self.entries)?;
+        std::fs::write(output_dir.join(SEARCH_INDEX_JSON), format!("{}\n", json))?;
+        std::fs::write(
+            output_dir.join(SEARCH_INDEX_JS),
+            format!("window.mdslidesSearchIndex = {};\n", json),
+        )?;
+        std::fs::write(output_dir.join(SEARCH_JS), SEARCH_WIDGET)?;
+        Ok(())
+    }
+}
+
+/// Get the text of a slide, without its heading or any Markdown syntax.
+fn slide_text(slide: &Slide) -> String {
+    let mut text = String::new();
+    let mut in_title = false;
+    for block in slide.blocks.iter() {
+        let markdown = match block {
+            Block::Markdown(markdown) => markdown,
+            Block::Diagram(diagram) => {
+                text.push_str(&diagram.alt_text());
+                text.push(' ');
+                continue;
+            }
+        };
+        for event in pulldown_cmark::Parser::new(markdown) {
+            match event {
+                pulldown_cmark::Event::Start(pulldown_cmark::Tag::Heading {
+                    level: pulldown_cmark::HeadingLevel::H1 | pulldown_cmark::HeadingLevel::H2,
+                    ..
+                }) => in_title = true,
+                pulldown_cmark::Event::End(pulldown_cmark::TagEnd::Heading(
+                    pulldown_cmark::HeadingLevel::H1 | pulldown_cmark::HeadingLevel::H2,
+                )) => in_title = false,
+                pulldown_cmark::Event::Text(words) | pulldown_cmark::Event::Code(words)
+                    if !in_title =>
+                {
+                    text.push_str(&words);
+                }
+                // Keep the words of separate paragraphs, list items and
+                // lines apart
+                pulldown_cmark::Event::End(
+                    pulldown_cmark::TagEnd::Paragraph
+                    | pulldown_cmark::TagEnd::Heading(_)
+                    | pulldown_cmark::TagEnd::CodeBlock
+                    | pulldown_cmark::TagEnd::Item
+                    | pulldown_cmark::TagEnd::BlockQuote(_)
+                    | pulldown_cmark::TagEnd::TableCell,
+                )
+                | pulldown_cmark::Event::SoftBreak
+                | pulldown_cmark::Event::HardBreak => text.push(' '),
+                _ => {}
+            }
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::slides;
+
+    #[test]
+    fn index() {
+        let slides = slides::split(
+            "# Pinning\n\n`Pin<&mut T>` stops *moves*.\n\n```rust\nlet x = pin!(f);\n```\n\n## Unpin\n\n```dot process alt=\"Pin diagram\"\ndigraph {}\n```\n",
+            &["dot"],
+        );
+        let mut index = SearchIndex::default();
+        index.add_deck("Async", "./async/pin.html", &slides, Backend::Reveal);
+        assert_eq!(
+            index.entries(),
+            [
+                SearchEntry {
+                    deck: "Async".to_owned(),
+                    url: "./async/pin.html#/0".to_owned(),
+                    slide: 1,
+                    heading: Some("Pinning".to_owned()),
+                    text: "Pin<&mut T> stops moves. let x = pin!(f);".to_owned(),
+                },
+                SearchEntry {
+                    deck: "Async".to_owned(),
+                    url: "./async/pin.html#/1".to_owned(),
+                    slide: 2,
+                    heading: Some("Unpin".to_owned()),
+                    text: "Pin diagram".to_owned(),
+                },
+            ]
+        );
+
+        // remark.js numbers each step of a slide
+        let slides = slides::split("# One\nA\n--\nB\n# Two\n", &[]);
+        let mut index = SearchIndex::default();
+        index.add_deck("Steps", "./steps.html", &slides, Backend::Remark);
+        let urls: Vec<&str> = index.entries().iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(urls, ["./steps.html#1", "./steps.html#3"]);
+    }
+}
@@ -0,0 +1,560 @@
+//! Splitting a chapter into individual slides
+//!
+//! Each `# Heading` or `## Subheading` in a chapter starts a new slide, as
+//! does an explicit `---` line. This module turns a chapter's Markdown into a
+//! list of [`Slide`]s, which the various outputs then work from.
+
+/// A single slide within a chapter
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Slide {
+    /// The text of the heading which starts this slide, if any
+    pub title: Option<String>,
+    /// The contents of the slide, in order
+    pub blocks: Vec<Block>,
+    /// Any speaker notes (the text following a `Note:` line)
+    pub notes: Option<String>,
+    /// Any CSS classes set with `<!-- .slide: class="..." -->`
+    pub classes: Vec<String>,
+    /// How long the slide takes to present, in seconds, if set with
+    /// `<!-- .slide: time="..." -->`
+    pub time: Option<u32>,
+}
+
+/// A piece of a slide
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Block {
+    /// Markdown text, to be passed through as-is
+    Markdown(String),
+    /// A diagram which we need to render
+    Diagram(Diagram),
+}
+
+/// A code block which mdslides renders into an image
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Diagram {
+    /// The language of the code block, e.g. `dot`
+    pub language: String,
+    /// Any words in the code fence after the language, e.g. `process` or
+    /// `alt="Some text"` (which is stored without the quotes)
+    pub attributes: Vec<String>,
+    /// The source code of the diagram
+    pub source: String,
+}
+
+impl Diagram {
+    /// Text describing the diagram, for the `alt` attribute of an image.
+    ///
+    /// Taken from an `alt="..."` attribute on the code fence, or else the
+    /// `label` of a Graphviz graph.
+    pub fn alt_text(&self) -> String {
+        if let Some(alt) = self
+            .attributes
+            .iter()
+            .find_map(|attribute| attribute.strip_prefix("alt="))
+        {
+            return alt.to_owned();
+        }
+        if self.language == "dot" {
+            // Node and edge labels are inside `[...]`, so only look for a
+            // `label = "..."` statement at the start of a line
+            let label = self.source.lines().find_map(|line| {
+                let value = line.trim().strip_prefix("label")?.trim_start();
+                let value = value.strip_prefix('=')?.trim().trim_end_matches(';');
+                value.strip_prefix('"')?.strip_suffix('"')
+            });
+            if let Some(label) = label {
+                return label.to_owned();
+            }
+        }
+        format!("{} diagram", self.language)
+    }
+}
+
+impl Slide {
+    fn new() -> Slide {
+        Slide {
+            title: None,
+            blocks: Vec::new(),
+            notes: None,
+            classes: Vec::new(),
+            time: None,
+        }
+    }
+
+    /// Iterate through the diagrams on this slide
+    pub fn diagrams(&self) -> impl Iterator<Item = &Diagram> {
+        self.blocks.iter().filter_map(|block| match block {
+            Block::Diagram(diagram) => Some(diagram),
+            Block::Markdown(_) => None,
+        })
+    }
+
+    /// How many `--` lines there are on this slide, outside code blocks.
+    ///
+    /// remark.js shows each part of the slide between these lines as a
+    /// separate step, with its own slide number.
+    pub fn increments(&self) -> usize {
+        let mut count = 0;
+        for block in self.blocks.iter() {
+            let Block::Markdown(text) = block else {
+                continue;
+            };
+            let mut in_code_block = false;
+            for line in text.lines() {
+                if line.starts_with("```") || line.starts_with("~~~") {
+                    in_code_block = !in_code_block;
+                } else if line == "--" && !in_code_block {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Add a line of Markdown to the slide
+    fn push_line(&mut self, line: &str) {
+        if let Some(notes) = self.notes.as_mut() {
+            notes.push_str(line);
+            notes.push('\n');
+            return;
+        }
+        if let Some(Block::Markdown(text)) = self.blocks.last_mut() {
+            text.push_str(line);
+            text.push('\n');
+        } else {
+            self.blocks.push(Block::Markdown(format!("{}\n", line)));
+        }
+    }
+}
+
+/// Separate a chapter's front matter from the rest of the chapter.
+///
+/// Front matter is written in TOML, between two `+++` lines at the very start
+/// of the chapter. If there is no front matter, an empty table is returned,
+/// along with the whole chapter.
+pub fn front_matter(markdown: &str) -> Result<(toml::Table, &str), toml::de::Error> {
+    let Some(rest) = markdown.strip_prefix("+++").and_then(|rest| {
+        rest.strip_prefix('\n')
+            .or_else(|| rest.strip_prefix("\r\n"))
+    }) else {
+        return Ok((toml::Table::new(), markdown));
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "+++" {
+            let table = toml::from_str(&rest[..offset])?;
+            return Ok((table, &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    // No closing +++, so this wasn't front matter after all
+    Ok((toml::Table::new(), markdown))
+}
+
+/// If this line is a `#` or `##` heading, get the heading text.
+fn heading_text(line: &str) -> Option<&str> {
+    line.strip_prefix("# ")
+        .or_else(|| line.strip_prefix("## "))
+        .map(str::trim)
+}
+
+/// The settings in a `<!-- .slide: ... -->` comment
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SlideAttributes {
+    /// The names in `class="..."`
+    classes: Vec<String>,
+    /// The value of `time="..."`, in seconds
+    time: Option<u32>,
+    /// The comment without `time`, if it sets anything we leave for the slide
+    /// framework
+    rest: Option<String>,
+}
+
+/// If this line is a `<!-- .slide: ... -->` comment, get the settings in it.
+///
+/// The `time` is always taken out. If the comment only sets classes, they are
+/// taken out too, otherwise the rest of the comment (classes included) is
+/// left for the slide framework, as it was written.
+fn slide_attributes(line: &str) -> Option<SlideAttributes> {
+    let attributes = line
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix(".slide:")?;
+    // Where the attributes start in the line
+    let offset = attributes.as_ptr() as usize - line.as_ptr() as usize;
+    let mut result = SlideAttributes::default();
+    let mut only_classes = true;
+    let mut time_ranges = Vec::new();
+    for (range, word) in attribute_words(attributes) {
+        match word.split_once('=') {
+            Some(("time", time)) => {
+                match crate::timing::parse_duration(time) {
+                    Some(seconds) => result.time = Some(seconds),
+                    None => {
+                        log::warn!("Ignoring slide time {:?}, as it isn't like 90s or 2m", time)
+                    }
+                }
+                // Take out the whitespace before it too
+                let start = attributes[..range.start].trim_end().len();
+                time_ranges.push(offset + start..offset + range.end);
+            }
+            Some(("class", classes)) => {
+                result
+                    .classes
+                    .extend(classes.split_whitespace().map(str::to_owned));
+            }
+            _ => only_classes = false,
+        }
+    }
+    let mut rest = line.to_owned();
+    // Go backwards, so taking out one `time` doesn't move the others
+    for range in time_ranges.into_iter().rev() {
+        rest.replace_range(range, "");
+    }
+    if !only_classes {
+        result.classes.clear();
+        result.rest = Some(rest);
+    }
+    Some(result)
+}
+
+/// Split the attributes in a `.slide:` comment into words, with where each
+/// word is.
+///
+/// Single or double quotes group words together, so `class='a b'` is one
+/// word, and the quotes are removed.
+fn attribute_words(attributes: &str) -> Vec<(std::ops::Range<usize>, String)> {
+    let mut words = Vec::new();
+    let mut word: Option<(usize, String)> = None;
+    let mut quote = None;
+    for (idx, c) in attributes.char_indices() {
+        if quote.is_none() && c.is_whitespace() {
+            if let Some((start, word)) = word.take() {
+                words.push((start..idx, word));
+            }
+            continue;
+        }
+        let (_, text) = word.get_or_insert_with(|| (idx, String::new()));
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            _ => text.push(c),
+        }
+    }
+    if let Some((start, word)) = word {
+        words.push((start..attributes.len(), word));
+    }
+    words
+}
+
+/// Split the Markdown source of a chapter into slides.
+///
+/// A `# Heading` or `## Subheading` starts a new slide, unless it is the first
+/// heading in the chapter (in which case any text above it is part of the
+/// first slide). A `---` line always starts a new slide. Lines inside code
+/// blocks are never treated as headings.
+///
+/// Code blocks with an info string like `dot process`, where the language is
+/// one of `diagram_languages`, are collected as [`Diagram`]s. A line starting
+/// with `Note:` turns the rest of the slide into
+/// speaker notes, and a `<!-- .slide: class="..." -->` comment sets the
+/// classes of the slide, again as with reveal.js. The same comment can give
+/// how long the slide takes to present, like `time="2m"`.
+pub fn split(markdown: &str, diagram_languages: &[&str]) -> Vec<Slide> {
+    let mut slides = Vec::new();
+    let mut current = Slide::new();
+    let mut seen_heading = false;
+    let mut in_code_block = false;
+    let mut collecting_diagram: Option<Diagram> = None;
+
+    for line in markdown.lines() {
+        if let Some(mut diagram) = collecting_diagram.take() {
+            if line == "```" {
+                // This is the end of a diagram
+                current.blocks.push(Block::Diagram(diagram));
+            } else {
+                diagram.source.push_str(line);
+                diagram.source.push('\n');
+                collecting_diagram = Some(diagram);
+            }
+            continue;
+        }
+
+        if line.starts_with("```") || line.starts_with("~~~") {
+            if !in_code_block && current.notes.is_none() {
+                if let Some(diagram) = diagram_fence(line, diagram_languages) {
+                    collecting_diagram = Some(diagram);
+                    continue;
+                }
+            }
+            in_code_block = !in_code_block;
+            current.push_line(line);
+            continue;
+        }
+
+        if in_code_block {
+            current.push_line(line);
+            continue;
+        }
+
+        if line == "---" {
+            slides.push(std::mem::replace(&mut current, Slide::new()));
+            continue;
+        }
+
+        if let Some(title) = heading_text(line) {
+            if seen_heading {
+                slides.push(std::mem::replace(&mut current, Slide::new()));
+            }
+            seen_heading = true;
+            if current.title.is_none() {
+                current.title = Some(title.to_owned());
+            }
+        }
+
+        if let Some(attributes) = slide_attributes(line).filter(|_| current.notes.is_none()) {
+            current.classes.extend(attributes.classes);
+            current.time = attributes.time.or(current.time);
+            if let Some(rest) = attributes.rest {
+                current.push_line(&rest);
+            }
+            continue;
+        }
+
+        if current.notes.is_none() {
+            if let Some(rest) = line.strip_prefix("Note:") {
+                let rest = rest.trim_start();
+                current.notes = Some(if rest.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}\n", rest)
+                });
+                continue;
+            }
+        }
+
+        current.push_line(line);
+    }
+
+    if let Some(diagram) = collecting_diagram {
+        log::warn!("Diagram code block was not closed");
+        current.blocks.push(Block::Diagram(diagram));
+    }
+    slides.push(current);
+    slides
+}
+
+/// Check if a code fence starts a diagram we should render.
+fn diagram_fence(line: &str, diagram_languages: &[&str]) -> Option<Diagram> {
+    let info = line.strip_prefix("```")?;
+    let mut words = info_words(info).into_iter();
+    let language = words.next()?;
+    let attributes: Vec<String> = words.collect();
+    if diagram_languages.contains(&language.as_str()) && attributes.iter().any(|a| a == "process") {
+        Some(Diagram {
+            language,
+            attributes,
+            source: String::new(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Split a code fence's info string into words.
+///
+/// Double quotes group words together, so `alt="A graph"` is one word, and
+/// the quotes are removed.
+fn info_words(info: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_quotes = false;
+    for c in info.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LANGUAGES: &[&str] = &["dot", "mermaid"];
+
+    #[test]
+    fn split_on_headings() {
+        let slides = split(
+            "# Page 1\n\n## Page 2\n\n---\n\nPage 3\n\n## Page 4\n",
+            LANGUAGES,
+        );
+        let titles: Vec<Option<&str>> = slides.iter().map(|s| s.title.as_deref()).collect();
+        assert_eq!(
+            titles,
+            [Some("Page 1"), Some("Page 2"), None, Some("Page 4")]
+        );
+        assert_eq!(
+            slides[2].blocks,
+            [Block::Markdown("\nPage 3\n\n".to_owned())]
+        );
+    }
+
+    #[test]
+    fn text_before_first_heading() {
+        let slides = split("Intro\n# Page 1\nText\n", LANGUAGES);
+        assert_eq!(slides.len(), 1);
+        assert_eq!(slides[0].title.as_deref(), Some("Page 1"));
+    }
+
+    #[test]
+    fn headings_in_code_blocks() {
+        let slides = split("# Page 1\n\n```rust\n# fn main() {}\n```\n", LANGUAGES);
+        assert_eq!(slides.len(), 1);
+    }
+
+    #[test]
+    fn diagrams_and_notes() {
+        let slides = split(
+            "# Page 1\n\n```dot process\ndigraph {}\n```\n\nNote: Say hello\nand wave\n## Page 2\n",
+            LANGUAGES,
+        );
+        assert_eq!(slides.len(), 2);
+        assert_eq!(
+            slides[0].diagrams().collect::<Vec<_>>(),
+            [&Diagram {
+                language: "dot".to_owned(),
+                attributes: vec!["process".to_owned()],
+                source: "digraph {}\n".to_owned(),
+            }]
+        );
+        assert_eq!(slides[0].notes.as_deref(), Some("Say hello\nand wave\n"));
+        assert_eq!(slides[1].notes, None);
+    }
+
+    #[test]
+    fn mermaid_diagrams() {
+        let slides = split(
+            "```mermaid process\ngraph LR\n```\n```mermaid\ngraph LR\n```\n",
+            LANGUAGES,
+        );
+        let languages: Vec<&str> = slides[0].diagrams().map(|d| d.language.as_str()).collect();
+        assert_eq!(languages, ["mermaid"]);
+        assert_eq!(
+            slides[0].blocks[1],
+            Block::Markdown("```mermaid\ngraph LR\n```\n".to_owned())
+        );
+    }
+
+    #[test]
+    fn front_matter_is_split() {
+        let (table, rest) = front_matter("+++\nlayout = \"title\"\n+++\n# Page 1\n").unwrap();
+        assert_eq!(table.get("layout").and_then(|v| v.as_str()), Some("title"));
+        assert_eq!(rest, "# Page 1\n");
+
+        let (table, rest) = front_matter("# Page 1\n+++\n").unwrap();
+        assert!(table.is_empty());
+        assert_eq!(rest, "# Page 1\n+++\n");
+    }
+
+    #[test]
+    fn classes() {
+        let slides = split(
+            "# Page 1\n<!-- .slide: class=\"center middle\" -->\nText\n",
+            LANGUAGES,
+        );
+        assert_eq!(slides[0].classes, ["center", "middle"]);
+        assert_eq!(
+            slides[0].blocks,
+            [Block::Markdown("# Page 1\nText\n".to_owned())]
+        );
+
+        let slides = split(
+            "# Page 1\n<!-- .slide: class=\"center\" time=\"1m30s\" -->\n# Page 2\n<!-- .slide: data-background=\"red\" time=\"2m\" -->\n",
+            LANGUAGES,
+        );
+        assert_eq!(slides[0].classes, ["center"]);
+        assert_eq!(slides[0].time, Some(90));
+        assert_eq!(slides[1].time, Some(120));
+        assert!(slides[1].classes.is_empty());
+        assert_eq!(
+            slides[1].blocks,
+            [Block::Markdown(
+                "# Page 2\n<!-- .slide: data-background=\"red\" -->\n".to_owned()
+            )]
+        );
+
+        let slides = split(
+            "# Page 1\n<!-- .slide: class=\"center\" data-state=\"intro\" time=\"30s\" -->\n",
+            LANGUAGES,
+        );
+        assert_eq!(slides[0].time, Some(30));
+        assert!(slides[0].classes.is_empty());
+        assert_eq!(
+            slides[0].blocks,
+            [Block::Markdown(
+                "# Page 1\n<!-- .slide: class=\"center\" data-state=\"intro\" -->\n".to_owned()
+            )]
+        );
+
+        // The rest of the comment is kept as written
+        let slides = split(
+            "# Page 1\n<!-- .slide: time='1m' class='a b' data-x=y -->\n",
+            LANGUAGES,
+        );
+        assert_eq!(slides[0].time, Some(60));
+        assert_eq!(
+            slides[0].blocks,
+            [Block::Markdown(
+                "# Page 1\n<!-- .slide: class='a b' data-x=y -->\n".to_owned()
+            )]
+        );
+
+        let slides = split("# Page 1\n<!-- .slide: class='a b' -->\n", LANGUAGES);
+        assert_eq!(slides[0].classes, ["a", "b"]);
+
+        // Comments in speaker notes don't change the slide
+        let slides = split(
+            "# Page 1\nNote: see\n<!-- .slide: class=\"big\" time=\"5m\" -->\n",
+            LANGUAGES,
+        );
+        assert!(slides[0].classes.is_empty());
+        assert_eq!(slides[0].time, None);
+        assert_eq!(
+            slides[0].notes.as_deref(),
+            Some("see\n<!-- .slide: class=\"big\" time=\"5m\" -->\n")
+        );
+    }
+
+    #[test]
+    fn increments() {
+        let slides = split(
+            "# Page 1\nFirst\n--\nSecond\n```\n--\n```\n--\nThird\n# Page 2\n",
+            LANGUAGES,
+        );
+        assert_eq!(slides[0].increments(), 2);
+        assert_eq!(slides[1].increments(), 0);
+    }
+
+    #[test]
+    fn alt_text() {
+        let slides = split(
+            "```dot process alt=\"A small graph\"\ndigraph {}\n```\n```dot process\ndigraph {\n  label = \"Flow\";\n  a [label=\"A\"];\n}\n```\n```mermaid process\ngraph TD\n```\n",
+            LANGUAGES,
+        );
+        let alt: Vec<String> = slides[0].diagrams().map(Diagram::alt_text).collect();
+        assert_eq!(alt, ["A small graph", "Flow", "mermaid diagram"]);
+    }
+}
@@ -0,0 +1,253 @@
+//! Templates for slide decks and index pages
+//!
+//! Templates are either *legacy* templates, where `$VARIABLES` are replaced
+//! with their values, or Jinja-style templates, which are rendered with
+//! [MiniJinja](https://docs.rs/minijinja).
+
+use std::path::{Path, PathBuf};
+
+use crate::{Backend, Error};
+
+/// The source of the built-in reveal.js slide template
+pub(crate) const REVEAL_TEMPLATE: &str = include_str!("templates/reveal.html");
+
+/// The source of the built-in remark.js slide template
+pub(crate) const REMARK_TEMPLATE: &str = include_str!("templates/remark.html");
+
+/// The source of the built-in index template
+pub(crate) const INDEX_TEMPLATE: &str = include_str!("templates/index.html");
+
+/// The source of the built-in agenda template
+pub(crate) const AGENDA_TEMPLATE: &str = include_str!("templates/agenda.html");
+
+/// The syntax a template is written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateSyntax {
+    /// Jinja if the template contains `{{` or `{%`, otherwise legacy
+    #[default]
+    Auto,
+    /// `$VARIABLE` substitution
+    Legacy,
+    /// Jinja-style, with `{{ variable }}`, `{% if %}`, `{% for %}`, etc
+    Jinja,
+}
+
+/// A template for a slide deck or an index page
+#[derive(Debug, Clone)]
+pub struct Template {
+    source: String,
+    syntax: TemplateSyntax,
+    dir: Option<PathBuf>,
+}
+
+impl Template {
+    /// Create a template from a string.
+    ///
+    /// Jinja templates created this way cannot `{% include %}` other files.
+    pub fn new(source: impl Into<String>, syntax: TemplateSyntax) -> Template {
+        let source = source.into();
+        let syntax = match syntax {
+            TemplateSyntax::Auto if source.contains("{{") || source.contains("{%") => {
+                TemplateSyntax::Jinja
+            }
+            TemplateSyntax::Auto => TemplateSyntax::Legacy,
+            other => other,
+        };
+        Template {
+            source,
+            syntax,
+            dir: None,
+        }
+    }
+
+    /// Load a template from a file.
+    ///
+    /// Jinja templates loaded this way can `{% include %}`, `{% import %}` or
+    /// `{% extends %}` other files, relative to the folder containing this one.
+    pub fn load(path: &Path, syntax: TemplateSyntax) -> Result<Template, Error> {
+        let source = std::fs::read_to_string(path)?;
+        let mut template = Template::new(source, syntax);
+        template.dir = path.parent().map(Path::to_owned);
+        Ok(template)
+    }
+
+    /// The built-in template for slide decks, for the given backend.
+    ///
+    /// Returns `None` if the backend doesn't use a template.
+    pub fn builtin_slides(backend: Backend) -> Option<Template> {
+        let source = match backend {
+            Backend::Reveal => REVEAL_TEMPLATE,
+            Backend::Remark => REMARK_TEMPLATE,
+            Backend::Marp => return None,
+        };
+        Some(Template::new(source, TemplateSyntax::Jinja))
+    }
+
+    /// The built-in template for the index page.
+    pub fn builtin_index() -> Template {
+        Template::new(INDEX_TEMPLATE, TemplateSyntax::Jinja)
+    }
+
+    /// The built-in template for agenda pages.
+    pub fn builtin_agenda() -> Template {
+        Template::new(AGENDA_TEMPLATE, TemplateSyntax::Jinja)
+    }
+
+    /// The syntax of this template - never [`TemplateSyntax::Auto`]
+    pub fn syntax(&self) -> TemplateSyntax {
+        self.syntax
+    }
+
+    /// Render the template.
+    ///
+    /// Legacy templates have each `$NAME` in `legacy_vars` replaced by its
+    /// value, so any escaping must already have been done. Jinja templates are
+    /// given `context`, with HTML escaping turned on - values made with
+    /// [`minijinja::Value::from_safe_string`], or passed through the `safe`
+    /// filter, are not escaped.
+    pub(crate) fn render(
+        &self,
+        legacy_vars: &[(&str, &str)],
+        context: minijinja::Value,
+    ) -> Result<String, Error> {
+        if self.syntax != TemplateSyntax::Jinja {
+            return Ok(substitute(&self.source, legacy_vars));
+        }
+        let mut env = minijinja::Environment::new();
+        env.set_auto_escape_callback(|_name| minijinja::AutoEscape::Html);
+        env.set_formatter(|out, state, value| {
+            // Leave `/` alone, unlike MiniJinja's own escaping, so URLs stay
+            // readable
+            match value.as_str() {
+                Some(text)
+                    if state.auto_escape() == minijinja::AutoEscape::Html && !value.is_safe() =>
+                {
+                    Ok(out.write_str(&escape_html(text))?)
+                }
+                _ => minijinja::escape_formatter(out, state, value),
+            }
+        });
+        env.set_keep_trailing_newline(true);
+        if let Some(dir) = self.dir.as_ref() {
+            env.set_loader(minijinja::path_loader(dir));
+        }
+        let template = env.template_from_str(&self.source)?;
+        Ok(template.render(context)?)
+    }
+}
+
+/// Escape text for use in HTML, including inside a quoted attribute.
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Replace each `$NAME` in `source` with its value.
+///
+/// This is done in a single pass, so values which happen to contain a
+/// `$NAME` are not themselves altered.
+fn substitute(source: &str, vars: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(idx) = rest.find('$') {
+        output.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+        match vars.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                output.push_str(value);
+                rest = &rest[name.len()..];
+            }
+            None => output.push('$'),
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn legacy() {
+        let template = Template::new("<h1>$TITLE</h1>\n$CONTENT $5", TemplateSyntax::Auto);
+        assert_eq!(template.syntax(), TemplateSyntax::Legacy);
+        let output = template
+            .render(
+                &[("TITLE", "A $CONTENT title"), ("CONTENT", "Costs $TITLE")],
+                minijinja::Value::UNDEFINED,
+            )
+            .unwrap();
+        assert_eq!(output, "<h1>A $CONTENT title</h1>\nCosts $TITLE $5");
+    }
+
+    #[test]
+    fn jinja() {
+        let template = Template::new(
+            "<h1>{{ title }}</h1>\n{% for x in items %}{{ x }}{% endfor %}\n{{ raw }}\n",
+            TemplateSyntax::Auto,
+        );
+        assert_eq!(template.syntax(), TemplateSyntax::Jinja);
+        let output = template
+            .render(
+                &[],
+                minijinja::context! {
+                    title => "Box<T>",
+                    items => [1, 2, 3],
+                    raw => minijinja::Value::from_safe_string("<b>".to_owned()),
+                },
+            )
+            .unwrap();
+        assert_eq!(output, "<h1>Box&lt;T&gt;</h1>\n123\n<b>\n");
+
+        let template = Template::new(
+            "<a href=\"{{ url }}\" title='{{ title }}'>{{ title }}</a> {{ title|safe }} {{ count }}",
+            TemplateSyntax::Jinja,
+        );
+        let output = template
+            .render(
+                &[],
+                minijinja::context! {
+                    url => "./part/box.html?a=1&b=\"2\"",
+                    title => "Box<T> & Rc<T>'s",
+                    count => 3,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            output,
+            "<a href=\"./part/box.html?a=1&amp;b=&quot;2&quot;\" title='Box&lt;T&gt; &amp; Rc&lt;T&gt;&#39;s'>Box&lt;T&gt; &amp; Rc&lt;T&gt;&#39;s</a> Box<T> & Rc<T>'s 3"
+        );
+    }
+
+    #[test]
+    fn builtin() {
+        assert!(Template::builtin_slides(Backend::Marp).is_none());
+        let template = Template::builtin_slides(Backend::Reveal).unwrap();
+        let output = template
+            .render(
+                &[],
+                minijinja::context! {
+                    title => "Chapter 1",
+                    content => minijinja::Value::from_safe_string("# Page 1".to_owned()),
+                    book => minijinja::context! { title => "Book" },
+                    reveal_js => "./reveal.js",
+                    theme_url => "theme.css",
+                },
+            )
+            .unwrap();
+        assert!(output.contains("<title>Chapter 1 - Book</title>"));
+        assert!(output.contains("<textarea data-template>\n# Page 1\n</textarea>"));
+    }
+}
@@ -0,0 +1,78 @@
+// Search widget for slides generated by mdslides.
+//
+// Put `<div id="mdslides-search"></div>` on the page, then load
+// `search-index.js` followed by this script.
+(function () {
+    "use strict";
+    var container = document.getElementById("mdslides-search");
+    var index = window.mdslidesSearchIndex;
+    if (!container || !index) {
+        return;
+    }
+    var maxResults = 50;
+
+    var input = document.createElement("input");
+    input.type = "search";
+    input.placeholder = "Search slides";
+    input.setAttribute("aria-label", "Search slides");
+    var results = document.createElement("ol");
+    results.className = "mdslides-search-results";
+    container.appendChild(input);
+    container.appendChild(results);
+
+    // A short piece of the slide's text, around the first search term
+    function snippet(text, term) {
+        var idx = text.toLowerCase().indexOf(term);
+        if (idx === -1) {
+            idx = 0;
+        }
+        var start = Math.max(0, idx - 40);
+        var end = Math.min(text.length, idx + term.length + 80);
+        return (start > 0 ? "..." : "") + text.slice(start, end) + (end < text.length ? "..." : "");
+    }
+
+    function search() {
+        var terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+        results.textContent = "";
+        if (terms.length === 0) {
+            return;
+        }
+        var found = 0;
+        for (var i = 0; i < index.length && found < maxResults; i++) {
+            var entry = index[i];
+            var haystack = [entry.deck, entry.heading || "", entry.text].join(" ").toLowerCase();
+            var matches = terms.every(function (term) {
+                return haystack.indexOf(term) !== -1;
+            });
+            if (!matches) {
+                continue;
+            }
+            found++;
+            var item = document.createElement("li");
+            var link = document.createElement("a");
+            link.href = entry.url;
+            link.textContent = entry.deck + ": " + (entry.heading || "Slide " + entry.slide);
+            item.appendChild(link);
+            if (entry.text) {
+                var text = document.createElement("p");
+                text.textContent = snippet(entry.text, terms[0]);
+                item.appendChild(text);
+            }
+            results.appendChild(item);
+        }
+        if (found === 0) {
+            var none = document.createElement("li");
+            none.textContent = "No slides found";
+            results.appendChild(none);
+        }
+    }
+
+    input.addEventListener("input", search);
+    input.addEventListener("keydown", function (event) {
+        // Enter jumps to the first result
+        var first = results.querySelector("a");
+        if (event.key === "Enter" && first) {
+            window.location.href = first.href;
+        }
+    });
+})();
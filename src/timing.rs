@@ -0,0 +1,128 @@
+//! Estimating how long slides take to present
+//!
+//! Each slide is given a fixed amount of time, plus time for its words, code
+//! and diagrams, using the rates in the `[timing]` table of `mdslides.toml`.
+//! A slide's own `time="..."` setting replaces the estimate.
+
+use crate::config::TimingConfig;
+use crate::slides::{Block, Slide};
+
+/// Estimate how long a slide takes to present, in seconds.
+pub fn slide_time(slide: &Slide, config: &TimingConfig) -> u32 {
+    if let Some(time) = slide.time {
+        return time;
+    }
+    let mut words = 0;
+    let mut code_lines = 0;
+    let mut in_code_block = false;
+    for block in slide.blocks.iter() {
+        let Block::Markdown(text) = block else {
+            continue;
+        };
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_code_block = !in_code_block;
+            } else if in_code_block {
+                if !trimmed.is_empty() {
+                    code_lines += 1;
+                }
+            } else {
+                words += trimmed
+                    .split_whitespace()
+                    .filter(|word| word.chars().any(char::is_alphanumeric))
+                    .count() as u32;
+            }
+        }
+    }
+    let diagrams = slide.diagrams().count() as u32;
+    config.seconds_per_slide
+        + (words * 60).div_ceil(config.words_per_minute.max(1))
+        + code_lines * config.seconds_per_code_line
+        + diagrams * config.seconds_per_diagram
+}
+
+/// Estimate how long a deck of slides takes to present, in seconds.
+pub fn deck_time(slides: &[Slide], config: &TimingConfig) -> u32 {
+    slides.iter().map(|slide| slide_time(slide, config)).sum()
+}
+
+/// Write a number of seconds like `1h 05m`, `12m 30s` or `45s`.
+pub fn format_duration(seconds: u32) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Read a duration like `90`, `90s`, `2m`, `1m30s` or `1h`, in seconds.
+///
+/// A number on its own is a number of seconds.
+pub fn parse_duration(text: &str) -> Option<u32> {
+    let text = text.trim();
+    if let Ok(seconds) = text.parse() {
+        return Some(seconds);
+    }
+    let mut total: u32 = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let scale = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let value: u32 = std::mem::take(&mut number).parse().ok()?;
+        total = total.checked_add(value.checked_mul(scale)?)?;
+    }
+    if !number.is_empty() || text.is_empty() {
+        // A number with no unit after a number with one, like `1m30`
+        return None;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::slides;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Some(90));
+        assert_eq!(parse_duration("2m"), Some(120));
+        assert_eq!(parse_duration("1m30s"), Some(90));
+        assert_eq!(parse_duration("1h"), Some(3600));
+        assert_eq!(parse_duration("1m30"), None);
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(750), "12m 30s");
+        assert_eq!(format_duration(3900), "1h 05m");
+    }
+
+    #[test]
+    fn estimates() {
+        let config = TimingConfig {
+            seconds_per_slide: 10,
+            words_per_minute: 60,
+            seconds_per_code_line: 5,
+            seconds_per_diagram: 30,
+        };
+        let slides = slides::split(
+            "# Hello world\n\n* One `two`\n\n```rust\nfn main() {\n}\n```\n\n```dot process\ndigraph {}\n```\n---\n<!-- .slide: time=\"2m\" -->\nLots of words here\n",
+            &["dot"],
+        );
+        // Four words, two lines of code and one diagram
+        assert_eq!(slide_time(&slides[0], &config), 10 + 4 + 10 + 30);
+        assert_eq!(slide_time(&slides[1], &config), 120);
+        assert_eq!(deck_time(&slides, &config), 174);
+    }
+}
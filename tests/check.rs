@@ -0,0 +1,108 @@
+//! Checks a book with missing files and broken links.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Write a file, creating its folder if required.
+fn write(path: &Path, contents: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+/// Serve `/ok` with a 200, and anything else with a 404, on a local port.
+fn mock_server() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request_line = String::new();
+            let mut reader = BufReader::new(&stream);
+            reader.read_line(&mut request_line).unwrap();
+            // Read the rest of the headers
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let status = if request_line.contains(" /ok ") {
+                "200 OK"
+            } else {
+                "404 Not Found"
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+        }
+    });
+    format!("http://{}", address)
+}
+
+#[test]
+fn check_book() {
+    let book_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("check_book");
+    if book_path.exists() {
+        std::fs::remove_dir_all(&book_path).unwrap();
+    }
+    let server = mock_server();
+    write(
+        &book_path.join("book.toml"),
+        "[book]\ntitle = \"Check\"\nsrc = \"src\"\n",
+    );
+    write(
+        &book_path.join("mdslides.toml"),
+        "[[agendas]]\ntitle = \"Course\"\nfile = \"agenda.html\"\n\
+         [[agendas.days]]\ntitle = \"Day 1\"\n\
+         sessions = [{ title = \"Morning\", chapters = [\"two.md\", \"./nowhere.md\"] }]\n",
+    );
+    write(
+        &book_path.join("src/SUMMARY.md"),
+        "# Summary\n\n- [One](./one.md)\n- [Two](./two.md)\n- [Missing](./missing.md)\n",
+    );
+    write(&book_path.join("src/img/there.svg"), "<svg></svg>");
+    write(
+        &book_path.join("src/one.md"),
+        &format!(
+            "# One\n\n![there](./img/there.svg) ![gone](./img/gone.png)\n\n\
+             [good](./two.md#second) [bad anchor](./two.md#third) [not a chapter](./other.md)\n\n\
+             [up]({server}/ok) [down]({server}/nope)\n"
+        ),
+    );
+    write(&book_path.join("src/two.md"), "# Two\n\n## Second\n");
+
+    let problems = mdslides::check(Some(&book_path), &mdslides::CheckOptions { urls: false })
+        .expect("check failed");
+    let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+    assert_eq!(problems.len(), 5, "{:#?}", problems);
+    assert!(problems[0].starts_with("./one.md: refers to \"./img/gone.png\""));
+    assert!(problems[1].starts_with("./one.md: links to \"./two.md#third\""));
+    assert!(problems[2].starts_with("./one.md: links to \"./other.md\""));
+    assert!(problems[3].starts_with("./missing.md: can't read"));
+    assert_eq!(
+        problems[4],
+        "mdslides.toml: agenda \"Course\" includes \"./nowhere.md\", which isn't a chapter in SUMMARY.md"
+    );
+
+    if cfg!(feature = "check-urls") {
+        let problems = mdslides::check(Some(&book_path), &mdslides::CheckOptions { urls: true })
+            .expect("check failed");
+        assert_eq!(problems.len(), 6);
+        assert!(problems[5]
+            .to_string()
+            .starts_with(&format!("./one.md: can't fetch {}/nope", server)));
+    }
+}
+
+#[test]
+fn check_nested_book() {
+    // Chapters in sub-folders, including one with a percent-encoded path
+    let problems = mdslides::check(
+        Some(Path::new("tests/nested_in")),
+        &mdslides::CheckOptions { urls: false },
+    )
+    .expect("check failed");
+    let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+    assert!(problems.is_empty(), "{:#?}", problems);
+}
@@ -0,0 +1,27 @@
+//! Sets up a new book with `mdslides init`, and checks it builds.
+
+use std::path::Path;
+
+#[test]
+fn init_and_build() {
+    let book_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("init_book");
+    if book_path.exists() {
+        std::fs::remove_dir_all(&book_path).unwrap();
+    }
+
+    mdslides::init(Some(&book_path), false).expect("init failed");
+    assert!(book_path.join("book.toml").exists());
+    assert!(book_path.join("src/example-slides.md").exists());
+
+    // Running it again mustn't overwrite anything, unless forced
+    let result = mdslides::init(Some(&book_path), false);
+    assert!(matches!(result, Err(mdslides::Error::FileExists(_))));
+    mdslides::init(Some(&book_path), true).expect("forced init failed");
+    let summary = std::fs::read_to_string(book_path.join("src/SUMMARY.md")).unwrap();
+    assert_eq!(summary.matches("example-slides.md").count(), 1);
+
+    mdslides::build(Some(&book_path), &mdslides::BuildOptions::default()).expect("build failed");
+    let deck = std::fs::read_to_string(book_path.join("slides/example-slides.html")).unwrap();
+    assert!(deck.contains("<title>Example Slides - init_book</title>"));
+    assert!(book_path.join("slides/index.html").exists());
+}
@@ -0,0 +1 @@
+// Stands in for reveal.js in the tests
@@ -4,23 +4,70 @@ use std::path::Path;
 
 #[test]
 fn build_slides() {
-    let slide_template_string = include_str!("./data_in/template.html");
-    let index_template_string = include_str!("./data_in/index_template.html");
+    let slide_template = mdslides::Template::new(
+        include_str!("./data_in/template.html"),
+        mdslides::TemplateSyntax::Auto,
+    );
+    let index_template = mdslides::Template::new(
+        include_str!("./data_in/index_template.html"),
+        mdslides::TemplateSyntax::Auto,
+    );
     println!("We are in: {}", std::env::current_dir().unwrap().display());
     mdslides::run(
         Some(Path::new("tests/data_in")),
         Path::new("tests/data_out"),
-        slide_template_string,
-        Some(index_template_string),
+        &slide_template,
+        Some(&index_template),
+        mdslides::Backend::Reveal,
     )
     .expect("mdslides failed");
 
-    let comparison = folder_compare::FolderCompare::new(
+    compare_output(
         Path::new("tests/data_out"),
         Path::new("tests/reference_out"),
-        &vec![],
-    )
-    .expect("failed to compare");
+    );
+}
+
+#[test]
+fn build_slides_from_config() {
+    // The templates come from `mdslides.toml`
+    let options = mdslides::BuildOptions {
+        output_dir: Some("tests/data_out_config".into()),
+        ..Default::default()
+    };
+    mdslides::build(Some(Path::new("tests/data_in")), &options).expect("mdslides failed");
+
+    compare_output(
+        Path::new("tests/data_out_config"),
+        Path::new("tests/reference_out"),
+    );
+}
+
+#[test]
+fn build_nested_slides() {
+    // Decks in sub-folders, with a local copy of reveal.js
+    let output_dir = Path::new("tests/nested_out");
+    if output_dir.exists() {
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+    let options = mdslides::BuildOptions {
+        output_dir: Some("tests/nested_out".into()),
+        ..Default::default()
+    };
+    mdslides::build(Some(Path::new("tests/nested_in")), &options).expect("mdslides failed");
+
+    compare_output(
+        Path::new("tests/nested_out"),
+        Path::new("tests/nested_reference_out"),
+    );
+    // Images are copied next to decks whose paths are percent-encoded
+    assert!(output_dir.join("my part/img/a.svg").is_file());
+}
+
+/// Check the slides we generated match the reference slides.
+fn compare_output(output_dir: &Path, reference_dir: &Path) {
+    let comparison = folder_compare::FolderCompare::new(output_dir, reference_dir, &vec![])
+        .expect("failed to compare");
     if !comparison.changed_files.is_empty() {
         for filename in comparison.changed_files {
             let contents = std::fs::read_to_string(&filename).unwrap();
@@ -42,3 +89,21 @@ fn build_slides() {
         panic!("Some new files found");
     }
 }
+
+#[test]
+fn dump_book() {
+    let mut output = Vec::new();
+    mdslides::dump(Some(Path::new("tests/data_in")), &mut output).expect("mdslides failed");
+    let dump: serde_json::Value = serde_json::from_slice(&output).expect("invalid JSON");
+
+    assert_eq!(dump["schema_version"], 1);
+    assert_eq!(dump["title"], "Sample Book");
+    assert_eq!(dump["index"][0]["heading"], "Summary");
+    let chapters = dump["chapters"].as_array().unwrap();
+    assert_eq!(chapters.len(), 2);
+    assert_eq!(chapters[0]["path"], "./chapter_1.md");
+    assert_eq!(chapters[0]["slide_count"], 4);
+    assert_eq!(chapters[0]["slides"][3]["title"], "Page 4");
+    assert_eq!(chapters[1]["slide_count"], 2);
+    assert_eq!(chapters[1]["slides"][0]["diagrams"][0]["language"], "dot");
+}
@@ -0,0 +1 @@
+window.mdslidesSearchIndex = [{"deck":"Chapter 1","url":"./chapter_1.html#/0","slide":1,"heading":"Page 1","text":""},{"deck":"Chapter 1","url":"./chapter_1.html#/1","slide":2,"heading":"Page 2","text":""},{"deck":"Chapter 1","url":"./chapter_1.html#/2","slide":3,"heading":null,"text":"Page 3"},{"deck":"Chapter 1","url":"./chapter_1.html#/3","slide":4,"heading":"Page 4","text":"See page 2 of chapter 2."},{"deck":"Chapter 2","url":"./chapter_2.html#/0","slide":1,"heading":"Page 1","text":"dot diagram"},{"deck":"Chapter 2","url":"./chapter_2.html#/1","slide":2,"heading":"Page 2","text":"A square"}];